@@ -86,7 +86,10 @@ pub trait IsServer<'a> {
         Self: std::marker::Sized,
     {
         match request {
-            methods::Client2Server::SuggestDifficulty() => Ok(None),
+            methods::Client2Server::SuggestDifficulty(suggest_difficulty) => {
+                self.handle_suggest_difficulty(&suggest_difficulty);
+                Ok(None)
+            }
             methods::Client2Server::Authorize(authorize) => {
                 let authorized = self.handle_authorize(&authorize);
                 if authorized {
@@ -182,6 +185,10 @@ pub trait IsServer<'a> {
     /// Indicates to the server that the client supports the mining.set_extranonce method.
     fn handle_extranonce_subscribe(&self);
 
+    /// Hint from the client about the share difficulty it would like to mine at. Servers that do
+    /// not manage per-client difficulty can ignore it, which is the default behaviour.
+    fn handle_suggest_difficulty(&mut self, _request: &client_to_server::SuggestDifficulty) {}
+
     fn is_authorized(&self, name: &str) -> bool;
 
     fn authorize(&mut self, name: &str);
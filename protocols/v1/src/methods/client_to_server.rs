@@ -654,7 +654,56 @@ impl From<InfoParams> for serde_json::Map<String, Value> {
     }
 }
 
-// mining.suggest_difficulty
+/// _mining.suggest_difficulty(difficulty)_
+///
+/// Used by the client to hint the server about the share difficulty it would like to mine at.
+/// The server is free to ignore the suggestion or to adjust it to its own constraints.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SuggestDifficulty {
+    pub id: u64,
+    pub value: f64,
+}
+
+impl From<SuggestDifficulty> for Message {
+    fn from(sd: SuggestDifficulty) -> Self {
+        let value: Value = sd.value.into();
+        Message::StandardRequest(StandardRequest {
+            id: sd.id,
+            method: "mining.suggest_difficulty".into(),
+            params: (&[value][..]).into(),
+        })
+    }
+}
+
+impl TryFrom<StandardRequest> for SuggestDifficulty {
+    type Error = ParsingMethodError;
+
+    fn try_from(msg: StandardRequest) -> Result<Self, Self::Error> {
+        let params = msg
+            .params
+            .as_array()
+            .ok_or_else(|| ParsingMethodError::not_array_from_value(msg.params.clone()))?;
+        let value = match &params[..] {
+            [a] => a
+                .as_f64()
+                .ok_or_else(|| ParsingMethodError::not_float_from_value(a.clone()))?,
+            _ => return Err(ParsingMethodError::wrong_args_from_value(msg.params)),
+        };
+        Ok(Self { id: msg.id, value })
+    }
+}
+
+#[test]
+fn test_suggest_difficulty_from_json_rpc() {
+    let client_message = r#"{"id":3,
+            "method": "mining.suggest_difficulty",
+            "params":[512]
+        }"#;
+    let client_message: StandardRequest = serde_json::from_str(client_message).unwrap();
+    let suggest_difficulty = SuggestDifficulty::try_from(client_message).unwrap();
+    assert_eq!(suggest_difficulty.id, 3);
+    assert_eq!(suggest_difficulty.value, 512.0);
+}
 
 // mining.suggest_target
 
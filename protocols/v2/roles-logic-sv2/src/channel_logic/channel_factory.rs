@@ -218,7 +218,11 @@ struct ChannelFactory {
     job_ids: Id,
     channel_to_group_id: HashMap<u32, u32, BuildNoHashHasher<u32>>,
     future_templates: HashMap<u32, NewTemplate<'static>, BuildNoHashHasher<u32>>,
-    keyset: Arc<Mutex<Sv2KeySet<'static>>>,
+    keyset: Arc<Mutex<Sv2KeySet>>,
+    // channel_id -> hash of the last share checked for that channel (big endian). Shares carry
+    // no locking key, the ehash is bound to the blinded messages of the share instead, so the
+    // hash is the only share metadata the success responses need
+    last_share_hashes: HashMap<u32, [u8; 32], BuildNoHashHasher<u32>>,
 }
 
 impl ChannelFactory {
@@ -839,6 +843,15 @@ impl ChannelFactory {
         let hash_ = header.block_hash();
         let hash = hash_.as_hash().into_inner();
 
+        // Hashpool: the share hash in big endian byte order indexes the blinded secrets and sizes
+        // the ehash issuance, so it must be set regardless of the log level
+        let mut share_hash = hash;
+        share_hash.reverse();
+        if let Share::Extended(extended_share) = &mut m {
+            extended_share.hash = share_hash.into();
+        }
+        self.last_share_hashes.insert(m.get_channel_id(), share_hash);
+
         if tracing::level_enabled!(tracing::Level::DEBUG)
             || tracing::level_enabled!(tracing::Level::TRACE)
         {
@@ -850,16 +863,7 @@ impl ChannelFactory {
             let mut upstream_target = upstream_target.to_vec();
             upstream_target.reverse();
             debug!("Upstream target: {:?}", upstream_target.to_vec().to_hex());
-            let mut hash = hash;
-            hash.reverse();
-            debug!("Hash           : {:?}", hash.to_vec().to_hex());
-            // Hashpool: set hash on the share for use in indexing the blinded secret
-            match &mut m {
-                Share::Extended(extended_share) => {
-                    extended_share.hash = hash.into();
-                }
-                Share::Standard(_) => (),
-            };
+            debug!("Hash           : {:?}", share_hash.to_vec().to_hex());
         }
         let hash: Target = hash.into();
 
@@ -978,6 +982,20 @@ impl ChannelFactory {
             },
         }
     }
+    /// Returns the hash of the last share checked for the given channel_id
+    fn last_share_hash(&self, channel_id: u32) -> Option<[u8; 32]> {
+        self.last_share_hashes.get(&channel_id).copied()
+    }
+    /// Forgets a channel whose downstream is gone, along with the hash of its last share
+    fn remove_channel(&mut self, channel_id: u32) {
+        if let Some(group_id) = self.channel_to_group_id.remove(&channel_id) {
+            let complete_id = GroupId::into_complete_id(group_id, channel_id);
+            self.standard_channels_for_non_hom_downstreams.remove(&complete_id);
+        }
+        self.standard_channels_for_hom_downstreams.remove(&channel_id);
+        self.extended_channels.remove(&channel_id);
+        self.last_share_hashes.remove(&channel_id);
+    }
     /// updates the downstream target for the given channel_id
     fn update_target_for_channel(&mut self, channel_id: u32, new_target: Target) -> Option<bool> {
         let channel = self.extended_channels.get_mut(&channel_id)?;
@@ -1007,7 +1025,7 @@ impl PoolChannelFactory {
         kind: ExtendedChannelKind,
         pool_coinbase_outputs: Vec<TxOut>,
         pool_signature: String,
-        keyset: Arc<Mutex<Sv2KeySet<'static>>>,
+        keyset: Arc<Mutex<Sv2KeySet>>,
     ) -> Self {
         let inner = ChannelFactory {
             ids,
@@ -1029,6 +1047,7 @@ impl PoolChannelFactory {
             channel_to_group_id: HashMap::with_hasher(BuildNoHashHasher::default()),
             future_templates: HashMap::with_hasher(BuildNoHashHasher::default()),
             keyset: keyset.clone(),
+            last_share_hashes: HashMap::with_hasher(BuildNoHashHasher::default()),
         };
 
         Self {
@@ -1306,6 +1325,18 @@ impl PoolChannelFactory {
     pub fn set_target(&mut self, new_target: &mut Target) {
         self.inner.kind.set_target(new_target);
     }
+
+    /// calls [`ChannelFactory::last_share_hash`]
+    /// Hash of the last share submitted on a channel, used to fill success messages.
+    pub fn last_share_hash(&self, channel_id: u32) -> Option<[u8; 32]> {
+        self.inner.last_share_hash(channel_id)
+    }
+
+    /// calls [`ChannelFactory::remove_channel`]
+    /// Removes the channel of a downstream that disconnected.
+    pub fn remove_channel(&mut self, channel_id: u32) {
+        self.inner.remove_channel(channel_id)
+    }
 }
 
 /// Used by proxies that want to open extended channls with upstream. If the proxy has job
@@ -1331,7 +1362,7 @@ impl ProxyExtendedChannelFactory {
         pool_coinbase_outputs: Option<Vec<TxOut>>,
         pool_signature: String,
         extended_channel_id: u32,
-        keyset: Arc<Mutex<Sv2KeySet<'static>>>,
+        keyset: Arc<Mutex<Sv2KeySet>>,
     ) -> Self {
         match &kind {
             ExtendedChannelKind::Proxy { .. } => {
@@ -1366,6 +1397,7 @@ impl ProxyExtendedChannelFactory {
             channel_to_group_id: HashMap::with_hasher(BuildNoHashHasher::default()),
             future_templates: HashMap::with_hasher(BuildNoHashHasher::default()),
             keyset: keyset.clone(),
+            last_share_hashes: HashMap::with_hasher(BuildNoHashHasher::default()),
         };
         ProxyExtendedChannelFactory {
             inner,
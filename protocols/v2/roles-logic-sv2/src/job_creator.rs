@@ -74,6 +74,7 @@ impl JobsCreators {
     ) -> Result<NewExtendedMiningJob<'static>, Error> {
         let server_tx_outputs = template.coinbase_tx_outputs.to_vec();
         let mut outputs = tx_outputs_to_costum_scripts(&server_tx_outputs);
+        let pool_outputs_len = pool_coinbase_outputs.len();
         pool_coinbase_outputs.append(&mut outputs);
 
         // This is to make sure that 0 is never used, so we can use 0 for
@@ -87,6 +88,7 @@ impl JobsCreators {
         new_extended_job(
             template,
             &mut pool_coinbase_outputs,
+            pool_outputs_len,
             pool_signature,
             next_job_id,
             version_rolling_allowed,
@@ -158,6 +160,7 @@ pub fn extended_job_from_custom_job(
     new_extended_job(
         &mut template,
         &mut outputs,
+        1,
         pool_signature,
         0,
         true,
@@ -171,19 +174,30 @@ pub fn extended_job_from_custom_job(
 /// Pool related arguments:
 ///
 /// * `coinbase_outputs`: coinbase output transactions specified by the pool.
+/// * `pool_outputs_len`: number of pool outputs at the start of `coinbase_outputs`. The first one
+///   receives the value remaining minus the value already assigned to the other pool outputs.
 /// * `job_id`: incremented job identifier specified by the pool.
 /// * `version_rolling_allowed`: boolean specified by the channel.
 /// * `extranonce_len`: extranonce length specified by the channel.
 fn new_extended_job(
     new_template: &mut NewTemplate,
     coinbase_outputs: &mut [TxOut],
+    pool_outputs_len: usize,
     pool_signature: String,
     job_id: u32,
     version_rolling_allowed: bool,
     extranonce_len: u8,
 ) -> Result<NewExtendedMiningJob<'static>, Error> {
-    coinbase_outputs[0].value = match new_template.coinbase_tx_value_remaining.checked_mul(1) {
-        //check that value_remaining is updated by TP
+    let assigned = coinbase_outputs
+        .get(1..pool_outputs_len)
+        .unwrap_or_default()
+        .iter()
+        .fold(0u64, |total, output| total.saturating_add(output.value));
+    coinbase_outputs[0].value = match new_template
+        .coinbase_tx_value_remaining
+        .checked_sub(assigned)
+    {
+        //check that value_remaining is updated by TP and covers the other pool outputs
         Some(result) => result,
         None => return Err(Error::ValueRemainingNotUpdated),
     };
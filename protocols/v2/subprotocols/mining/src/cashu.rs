@@ -1,5 +1,4 @@
-use cdk::{amount::{Amount, AmountStr}, nuts::{BlindSignature, BlindedMessage, CurrencyUnit, KeySet, PreMintSecrets, PublicKey}};
-use core::array;
+use cdk::{amount::{Amount, AmountStr}, nuts::{BlindSignature, BlindSignatureDleq, BlindedMessage, CurrencyUnit, KeySet, PreMintSecrets, PublicKey, SecretKey}};
 use std::{collections::BTreeMap, convert::{TryFrom, TryInto}};
 pub use std::error::Error;
 
@@ -100,10 +99,16 @@ impl TryFrom<PreMintSecrets> for BlindedMessageSet {
     }
 }
 
+/// Wire form of a blind signature, 97 bytes since the DLEQ scalars were added. Older peers send
+/// 33 byte items, a `SubmitSharesSuccess` from them fails to decode, so the pool and translator
+/// must be upgraded together.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Sv2BlindSignature<'decoder> {
     pub parity_bit: bool,
     pub blind_signature: PubKey<'decoder>,
+    // NUT-12 DLEQ proof scalars, all zeros when the mint did not provide a proof
+    pub dleq_e: U256<'decoder>,
+    pub dleq_s: U256<'decoder>,
 }
 
 impl<'decoder> Default for Sv2BlindSignature<'decoder> {
@@ -111,6 +116,8 @@ impl<'decoder> Default for Sv2BlindSignature<'decoder> {
         Self {
             parity_bit: false,
             blind_signature: PubKey::from([0u8; 32]),
+            dleq_e: U256::from([0u8; 32]),
+            dleq_s: U256::from([0u8; 32]),
         }
     }
 }
@@ -120,165 +127,64 @@ pub type Sv2BlindSignatureSetWire<'decoder> = WireArray<'decoder>;
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Sv2SigningKey<'decoder> {
-    pub amount: u64,
     pub parity_bit: bool,
     pub pubkey: PubKey<'decoder>,
 }
 
 impl<'decoder> Default for Sv2SigningKey<'decoder> {
     fn default() -> Self {
-        Self { 
-            amount: Default::default(),
-            parity_bit: Default::default(),
-            pubkey: PubKey::from(<[u8; 32]>::from([0_u8; 32])),
+        Self {
+            parity_bit: false,
+            pubkey: PubKey::from([0u8; 32]),
         }
     }
 }
 
-// Wire type for inter-role communication
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub struct Sv2KeySetWire<'decoder> {
-    pub id: u64,
-    pub keys: B064K<'decoder>,
-}
-
-// Domain type for in-role usage
+/// Mint public key used to sign the given amount, the domain item of a keyset
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Sv2KeySet<'a> {
-    pub id: u64,
-    pub keys: [Sv2SigningKey<'a>; 64],
-}
-
-impl<'a> Sv2KeySet<'a> {
-    pub const KEY_SIZE: usize = 41;
-    pub const NUM_KEYS: usize = 64;
-}
-
-impl<'a> TryFrom<Sv2KeySetWire<'a>> for [Sv2SigningKey<'a>; 64] {
-    type Error = binary_sv2::Error;
-
-    fn try_from(wire: Sv2KeySetWire<'a>) -> Result<Self, Self::Error> {
-        let raw = wire.keys.inner_as_ref();
-        if raw.len() != Sv2KeySet::KEY_SIZE * Sv2KeySet::NUM_KEYS {
-            return Err(binary_sv2::Error::DecodableConversionError);
-        }
-
-        let mut keys = array::from_fn(|_| Sv2SigningKey::default());
-        for (i, chunk) in raw.chunks(Sv2KeySet::KEY_SIZE).enumerate() {
-            let mut buffer = [0u8; Sv2KeySet::KEY_SIZE];
-            buffer.copy_from_slice(chunk);
-            keys[i] = Sv2SigningKey::from_bytes(&mut buffer)
-                .map_err(|_| binary_sv2::Error::DecodableConversionError)?
-                .into_static();
-        }
-        Ok(keys)
-    }
+pub struct SigningKey {
+    pub amount: Amount,
+    pub keyset_id: cdk::nuts::nut02::Id,
+    pub pubkey: PublicKey,
 }
 
-impl<'a> TryFrom<&[Sv2SigningKey<'a>; 64]> for Sv2KeySetWire<'a> {
-    type Error = binary_sv2::Error;
-
-    fn try_from(keys: &[Sv2SigningKey<'a>; 64]) -> Result<Self, Self::Error> {
-        let mut buffer = [0u8; Sv2KeySet::KEY_SIZE * Sv2KeySet::NUM_KEYS];
-        for (i, key) in keys.iter().enumerate() {
-            let start = i * Sv2KeySet::KEY_SIZE;
-            let end = start + Sv2KeySet::KEY_SIZE;
-            key.clone()
-                .to_bytes(&mut buffer[start..end])
-                .map_err(|_| binary_sv2::Error::DecodableConversionError)?;
-        }
-        let encoded_keys = B064K::try_from(buffer.to_vec())
-            .map_err(|_| binary_sv2::Error::DecodableConversionError)?;
+pub type Sv2KeySet = DomainArray<SigningKey>;
+pub type Sv2KeySetWire<'decoder> = WireArray<'decoder>;
 
-        Ok(Sv2KeySetWire {
-            id: 0, // ID can be set later by the caller
-            keys: encoded_keys,
-        })
-    }
-}
-
-impl<'a> From<Sv2KeySet<'a>> for Sv2KeySetWire<'a> {
-    fn from(domain: Sv2KeySet<'a>) -> Self {
-        (&domain.keys).try_into()
-            .expect("Encoding keys to Sv2KeySetWire should not fail")
-    }
-}
-
-impl<'a> TryFrom<Sv2KeySetWire<'a>> for Sv2KeySet<'a> {
-    type Error = binary_sv2::Error;
-
-    fn try_from(wire: Sv2KeySetWire<'a>) -> Result<Self, Self::Error> {
-        let keys: [Sv2SigningKey<'a>; 64] = wire.clone().try_into()?;
-        Ok(Sv2KeySet {
-            id: wire.id,
-            keys,
-        })
-    }
-}
-
-impl<'a> Default for Sv2KeySet<'a> {
-    fn default() -> Self {
-        let default_key = Sv2SigningKey::default();
-        let keys = array::from_fn(|_| default_key.clone());
-        Sv2KeySet { id: 0, keys }
-    }
-}
-
-impl<'a> TryFrom<KeySet> for Sv2KeySet<'a> {
+impl TryFrom<KeySet> for Sv2KeySet {
     type Error = Box<dyn Error>;
 
     fn try_from(value: KeySet) -> Result<Self, Self::Error> {
-        let id: u64 = KeysetId(value.id).into();
-
-        let mut sv2_keys = Vec::with_capacity(64);
-        for (amount_str, public_key) in value.keys.keys().iter() {
-            let mut pubkey_bytes = public_key.to_bytes();
-            let (parity_byte, pubkey_data) = pubkey_bytes.split_at_mut(1);
-            let parity_bit = parity_byte[0] == 0x03;
-
-            let pubkey = PubKey::from_bytes(pubkey_data)
-                .map_err(|_| "Failed to parse public key")?
-                .into_static();
-
-            let signing_key = Sv2SigningKey {
-                amount: amount_str.inner().into(),
-                parity_bit,
-                pubkey,
-            };
-            sv2_keys.push(signing_key);
-        }
-
-        // sanity check
-        if sv2_keys.len() != 64 {
-            return Err("Expected KeySet to have exactly 64 keys".into());
+        let mut keyset = Sv2KeySet::new(KeysetId(value.id).into());
+        for (amount_str, pubkey) in value.keys.keys().iter() {
+            let amount = amount_str.inner();
+            let amount_value: u64 = amount.into();
+            // every amount must map to a slot in the 64-element array
+            if !amount_value.is_power_of_two() {
+                return Err(format!("Invalid keyset amount {}", amount_value).into());
+            }
+            keyset.insert(SigningKey {
+                amount,
+                keyset_id: value.id,
+                pubkey: *pubkey,
+            });
         }
-
-        let keys: [Sv2SigningKey<'a>; 64] = sv2_keys
-            .try_into()
-            .map_err(|_| "Failed to convert Vec<Sv2SigningKey> into array")?;
-
-        Ok(Sv2KeySet { id, keys })
+        Ok(keyset)
     }
 }
 
-impl<'a> TryFrom<Sv2KeySet<'a>> for KeySet {
+impl TryFrom<Sv2KeySet> for KeySet {
     type Error = Box<dyn Error>;
 
     fn try_from(value: Sv2KeySet) -> Result<Self, Self::Error> {
-        let id = *KeysetId::try_from(value.id)?;
+        let id = *KeysetId::try_from(value.keyset_id)?;
 
-        let mut keys_map: BTreeMap<AmountStr, PublicKey> = BTreeMap::new();
-        for signing_key in value.keys.iter() {
-            let amount_str = AmountStr::from(Amount::from(signing_key.amount));
-
-            let mut pubkey_bytes = [0u8; 33];
-            pubkey_bytes[0] = if signing_key.parity_bit { 0x03 } else { 0x02 };
-            pubkey_bytes[1..].copy_from_slice(&signing_key.pubkey.inner_as_ref());
-            
-            let public_key = PublicKey::from_slice(&pubkey_bytes)?;
-    
-            keys_map.insert(amount_str, public_key);
-        }
+        let keys_map: BTreeMap<AmountStr, PublicKey> = value
+            .items
+            .iter()
+            .flatten()
+            .map(|key| (AmountStr::from(key.amount), key.pubkey))
+            .collect();
 
         Ok(KeySet {
             id,
@@ -313,8 +219,8 @@ fn amount_to_index(amount: u64) -> usize {
     amount.trailing_zeros() as usize
 }
 
-const WIRE_ITEM_SIZE: usize = 33;
-const NUM_MESSAGES: usize = 64;
+/// Number of power-of-two denominations a keyset, blinded message set or signature set can carry
+pub const NUM_MESSAGES: usize = 64;
 
 /// common trait implemented by domain items
 /// allowing them to be stored in a 64-element array
@@ -322,11 +228,16 @@ const NUM_MESSAGES: usize = 64;
 pub trait DomainItem<'decoder>: Clone {
     type WireType: Default + Clone + PartialEq + Eq + Serialize + Deserialize<'decoder>;
 
+    /// Encoded size in bytes of a single `WireType`
+    const WIRE_ITEM_SIZE: usize;
+
+    /// Converts a wire item back to its domain form, failing on bytes that are not a valid key
+    /// or scalar since wire items come from the network.
     fn from_wire(
         wire_obj: Self::WireType,
         keyset_id: cdk::nuts::nut02::Id,
         amount_index: usize,
-    ) -> Self;
+    ) -> Result<Self, binary_sv2::Error>;
 
     fn to_wire(&self) -> Self::WireType;
 
@@ -361,6 +272,12 @@ impl<T: for<'decoder> DomainItem<'decoder>> DomainArray<T> {
     }
 }
 
+impl<T: for<'decoder> DomainItem<'decoder>> Default for DomainArray<T> {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
 /// wire struct for transmitting 64 domain items in a single B064K
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct WireArray<'decoder> {
@@ -384,11 +301,12 @@ where
     for<'d> T: DomainItem<'d>,
 {
     fn from(domain: DomainArray<T>) -> Self {
-        let mut buffer = vec![0u8; WIRE_ITEM_SIZE * NUM_MESSAGES];
+        let item_size = <T as DomainItem<'static>>::WIRE_ITEM_SIZE;
+        let mut buffer = vec![0u8; item_size * NUM_MESSAGES];
 
         for (i, maybe_item) in domain.items.iter().enumerate() {
-            let offset = i * WIRE_ITEM_SIZE;
-            let chunk = &mut buffer[offset..offset + WIRE_ITEM_SIZE];
+            let offset = i * item_size;
+            let chunk = &mut buffer[offset..offset + item_size];
 
             // Convert the domain item to wire form, or use the default if None.
             let wire_obj = maybe_item
@@ -417,8 +335,8 @@ where
 
     fn try_from(wire: WireArray<'_>) -> Result<Self, Self::Error> {
         let raw = wire.encoded_data.inner_as_ref();
-        // TODO evaluate T::WireType::SIZE as an alternative to this constant
-        let expected_len = WIRE_ITEM_SIZE * NUM_MESSAGES;
+        let item_size = <T as DomainItem<'static>>::WIRE_ITEM_SIZE;
+        let expected_len = item_size * NUM_MESSAGES;
         if raw.len() != expected_len {
             return Err(binary_sv2::Error::DecodableConversionError);
         }
@@ -428,15 +346,14 @@ where
 
         let mut result = DomainArray::new(wire.keyset_id);
 
-        for (i, chunk) in raw.chunks(WIRE_ITEM_SIZE).enumerate() {
-            let mut buf = [0u8; WIRE_ITEM_SIZE];
-            buf.copy_from_slice(chunk);
+        for (i, chunk) in raw.chunks(item_size).enumerate() {
+            let mut buf = chunk.to_vec();
 
             let wire_item = T::WireType::from_bytes(&mut buf)
                 .map_err(|_| binary_sv2::Error::DecodableConversionError)?;
 
             if wire_item != T::WireType::default() {
-                let domain_item = T::from_wire(wire_item, *keyset_id_obj, i);
+                let domain_item = T::from_wire(wire_item, *keyset_id_obj, i)?;
                 result.items[i] = Some(domain_item);
             }
         }
@@ -448,25 +365,29 @@ where
 impl<'decoder> DomainItem<'decoder> for BlindedMessage {
     type WireType = Sv2BlindedMessage<'decoder>;
 
+    // 1 byte parity + 32 byte pubkey
+    const WIRE_ITEM_SIZE: usize = 33;
+
     fn from_wire(
         wire_obj: Self::WireType,
         keyset_id: cdk::nuts::nut02::Id,
         amount_index: usize,
-    ) -> Self {
+    ) -> Result<Self, binary_sv2::Error> {
         let amount = Amount::from(index_to_amount(amount_index));
         let mut pubkey_bytes = [0u8; 33];
         pubkey_bytes[0] = if wire_obj.parity_bit { 0x03 } else { 0x02 };
         pubkey_bytes[1..].copy_from_slice(&wire_obj.blinded_secret.inner_as_ref());
 
         let blinded_secret =
-            cdk::nuts::PublicKey::from_slice(&pubkey_bytes).expect("Invalid pubkey bytes");
+            cdk::nuts::PublicKey::from_slice(&pubkey_bytes)
+                .map_err(|_| binary_sv2::Error::DecodableConversionError)?;
 
-        BlindedMessage {
+        Ok(BlindedMessage {
             amount,
             keyset_id,
             blinded_secret,
             witness: None,
-        }
+        })
     }
 
     fn to_wire(&self) -> Self::WireType {
@@ -490,25 +411,42 @@ impl<'decoder> DomainItem<'decoder> for BlindedMessage {
 impl<'decoder> DomainItem<'decoder> for BlindSignature {
     type WireType = Sv2BlindSignature<'decoder>;
 
+    // 1 byte parity + 32 byte pubkey + two 32 byte DLEQ scalars
+    const WIRE_ITEM_SIZE: usize = 97;
+
     fn from_wire(
         wire_obj: Self::WireType,
         keyset_id: cdk::nuts::nut02::Id,
         amount_index: usize,
-    ) -> Self {
+    ) -> Result<Self, binary_sv2::Error> {
         let amount = Amount::from(index_to_amount(amount_index));
         let mut pubkey_bytes = [0u8; 33];
         pubkey_bytes[0] = if wire_obj.parity_bit { 0x03 } else { 0x02 };
         pubkey_bytes[1..].copy_from_slice(&wire_obj.blind_signature.inner_as_ref());
 
         let signature =
-            cdk::nuts::PublicKey::from_slice(&pubkey_bytes).expect("Invalid pubkey bytes");
+            cdk::nuts::PublicKey::from_slice(&pubkey_bytes)
+                .map_err(|_| binary_sv2::Error::DecodableConversionError)?;
 
-        BlindSignature {
+        let e = wire_obj.dleq_e.inner_as_ref();
+        let s = wire_obj.dleq_s.inner_as_ref();
+        let dleq = if e.iter().chain(s.iter()).all(|b| *b == 0) {
+            None
+        } else {
+            Some(BlindSignatureDleq {
+                e: SecretKey::from_slice(e)
+                    .map_err(|_| binary_sv2::Error::DecodableConversionError)?,
+                s: SecretKey::from_slice(s)
+                    .map_err(|_| binary_sv2::Error::DecodableConversionError)?,
+            })
+        };
+
+        Ok(BlindSignature {
             amount,
             keyset_id,
             c: signature,
-            dleq: None,
-        }
+            dleq,
+        })
     }
 
     fn to_wire(&self) -> Self::WireType {
@@ -516,11 +454,18 @@ impl<'decoder> DomainItem<'decoder> for BlindSignature {
         let parity_bit = pubkey_bytes[0] == 0x03;
         let pubkey_data = &mut pubkey_bytes[1..];
 
+        let (dleq_e, dleq_s) = match &self.dleq {
+            Some(dleq) => (dleq.e.to_secret_bytes(), dleq.s.to_secret_bytes()),
+            None => ([0u8; 32], [0u8; 32]),
+        };
+
         Sv2BlindSignature {
             parity_bit,
             blind_signature: PubKey::from_bytes(pubkey_data)
                 .expect("Invalid pubkey data")
                 .into_static(),
+            dleq_e: U256::from(dleq_e),
+            dleq_s: U256::from(dleq_s),
         }
     }
 
@@ -529,104 +474,135 @@ impl<'decoder> DomainItem<'decoder> for BlindSignature {
     }
 }
 
-#[cfg(test)]
-pub mod tests {
-    use super::*;
-    use rand::Rng;
+impl<'decoder> DomainItem<'decoder> for SigningKey {
+    type WireType = Sv2SigningKey<'decoder>;
 
-    fn get_random_signing_key() -> Sv2SigningKey<'static> {
-        let mut rng = rand::thread_rng();
+    // 1 byte parity + 32 byte pubkey
+    const WIRE_ITEM_SIZE: usize = 33;
 
-        let mut pubkey_bytes = [0u8; 32];
-        rng.fill(&mut pubkey_bytes[..]);
-
-        Sv2SigningKey {
-            amount: rng.gen::<u64>(),
-            pubkey: PubKey::from_bytes(&mut pubkey_bytes).unwrap().into_static(),
-            parity_bit: rng.gen(),
-        }
-    }
+    fn from_wire(
+        wire_obj: Self::WireType,
+        keyset_id: cdk::nuts::nut02::Id,
+        amount_index: usize,
+    ) -> Result<Self, binary_sv2::Error> {
+        let amount = Amount::from(index_to_amount(amount_index));
+        let mut pubkey_bytes = [0u8; 33];
+        pubkey_bytes[0] = if wire_obj.parity_bit { 0x03 } else { 0x02 };
+        pubkey_bytes[1..].copy_from_slice(&wire_obj.pubkey.inner_as_ref());
 
-    fn get_random_keyset() -> Sv2KeySet<'static> {
-        let mut rng = rand::thread_rng();
-    
-        let mut keys: [Sv2SigningKey<'static>; NUM_MESSAGES] = array::from_fn(|_| get_random_signing_key());
-        for i in 0..NUM_MESSAGES {
-            keys[i] = get_random_signing_key();
-        }
+        let pubkey =
+            cdk::nuts::PublicKey::from_slice(&pubkey_bytes)
+                .map_err(|_| binary_sv2::Error::DecodableConversionError)?;
 
-        Sv2KeySet {
-            // TODO this is an invalid keyset_id, does it matter?
-            id: rng.gen::<u64>(),
-            keys,
-        }
+        Ok(SigningKey {
+            amount,
+            keyset_id,
+            pubkey,
+        })
     }
 
-    fn get_random_signature() -> Sv2BlindSignature<'static> {
-        let mut rng = rand::thread_rng();
-
-        let mut signature_bytes = [0u8; 32];
-        rng.fill(&mut signature_bytes[..]);
+    fn to_wire(&self) -> Self::WireType {
+        let mut pubkey_bytes = self.pubkey.to_bytes();
+        let parity_bit = pubkey_bytes[0] == 0x03;
+        let pubkey_data = &mut pubkey_bytes[1..];
 
-        Sv2BlindSignature {
-            blind_signature: PubKey::from_bytes(&mut signature_bytes).unwrap().into_static(),
-            parity_bit: rng.gen(),
+        Sv2SigningKey {
+            parity_bit,
+            pubkey: PubKey::from_bytes(pubkey_data)
+                .expect("Invalid pubkey data")
+                .into_static(),
         }
     }
 
-    fn get_random_sigset() -> Sv2BlindSignatureSet<'static> {
-        let mut rng = rand::thread_rng();
-
-        let mut sigs: [Sv2BlindSignature<'static>; NUM_MESSAGES] = array::from_fn(|_| get_random_signature());
-        for i in 0..NUM_MESSAGES {
-            sigs[i] = get_random_signature();
-        }
-
-        Sv2BlindSignatureSet {
-            keyset_id: rng.gen::<u64>(),
-            signatures: sigs,
-        }
+    fn get_amount(&self) -> u64 {
+        self.amount.into()
     }
+}
 
-    fn get_random_blinded_message() -> Sv2BlindedMessage<'static> {
-        let mut rng = rand::thread_rng();
 
-        let mut pubkey_bytes = [0u8; 32];
-        rng.fill(&mut pubkey_bytes[..]);
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use cdk::{
+        dhke::{blind_message, sign_message},
+        nuts::SecretKey,
+    };
+    use rand::Rng;
 
-        Sv2BlindedMessage {
-            blinded_secret: PubKey::from_bytes(&mut pubkey_bytes).unwrap().into_static(),
-            parity_bit: rng.gen(),
-        }
+    fn get_random_keyset_id() -> cdk::nuts::nut02::Id {
+        // the leading byte is the keyset version and must be zero
+        let id = rand::thread_rng().gen::<u64>() >> 8;
+        *KeysetId::try_from(id).unwrap()
     }
 
-    fn get_random_msgset() -> Sv2BlindedMessageSet<'static> {
-        let mut rng = rand::thread_rng();
-
-        let mut messages: [Sv2BlindedMessage<'static>; NUM_MESSAGES] = array::from_fn(|_| get_random_blinded_message());
+    fn get_random_keyset() -> Sv2KeySet {
+        let keyset_id = get_random_keyset_id();
+        let mut keyset = Sv2KeySet::new(KeysetId(keyset_id).into());
         for i in 0..NUM_MESSAGES {
-            messages[i] = get_random_blinded_message();
+            keyset.insert(SigningKey {
+                amount: Amount::from(index_to_amount(i)),
+                keyset_id,
+                pubkey: SecretKey::generate().public_key(),
+            });
         }
-
-        Sv2BlindedMessageSet {
-            keyset_id: rng.gen::<u64>(),
-            items: messages,
+        keyset
+    }
+
+    fn get_random_msgset() -> BlindedMessageSet {
+        let keyset_id = get_random_keyset_id();
+        let mut messages = BlindedMessageSet::new(KeysetId(keyset_id).into());
+        // leave some slots empty to exercise the default wire item
+        for i in (0..NUM_MESSAGES).step_by(3) {
+            let (blinded_secret, _) = blind_message(&[i as u8; 32], None).unwrap();
+            messages.insert(BlindedMessage {
+                amount: Amount::from(index_to_amount(i)),
+                keyset_id,
+                blinded_secret,
+                witness: None,
+            });
+        }
+        messages
+    }
+
+    fn get_random_sigset() -> BlindSignatureSet {
+        let keyset_id = get_random_keyset_id();
+        let mut signatures = BlindSignatureSet::new(KeysetId(keyset_id).into());
+        for i in (0..NUM_MESSAGES).step_by(2) {
+            let (blinded_secret, _) = blind_message(&[i as u8; 32], None).unwrap();
+            let mint_key = SecretKey::generate();
+            let c = sign_message(&mint_key, &blinded_secret).unwrap();
+            // alternate signatures with and without a DLEQ proof
+            let dleq = (i % 4 == 0).then(|| BlindSignatureDleq {
+                e: SecretKey::generate(),
+                s: SecretKey::generate(),
+            });
+            signatures.insert(BlindSignature {
+                amount: Amount::from(index_to_amount(i)),
+                keyset_id,
+                c,
+                dleq,
+            });
         }
+        signatures
     }
 
     #[test]
     fn test_sv2_signing_key_encode_decode() {
-        let original_key = get_random_signing_key();
+        let original_key = SigningKey {
+            amount: Amount::from(1),
+            keyset_id: get_random_keyset_id(),
+            pubkey: SecretKey::generate().public_key(),
+        }
+        .to_wire();
 
         // encode it
-        let mut buffer = [0u8; 41]; // 8 byte amount + 33 byte pubkey
+        let mut buffer = [0u8; <SigningKey as DomainItem<'static>>::WIRE_ITEM_SIZE];
         let encoded_size = original_key.clone().to_bytes(&mut buffer).unwrap();
-        assert_eq!(encoded_size, 41);
+        assert_eq!(encoded_size, buffer.len());
 
         // decode it
         let decoded_key = Sv2SigningKey::from_bytes(&mut buffer).unwrap();
-        assert_eq!(original_key.amount, decoded_key.amount);
-        assert_eq!(original_key.pubkey, decoded_key.pubkey);
+        assert_eq!(original_key, decoded_key);
     }
 
     #[test]
@@ -635,27 +611,49 @@ pub mod tests {
         let wire_keyset: Sv2KeySetWire = original_keyset.clone().into();
         let domain_keyset: Sv2KeySet = wire_keyset.clone().try_into().unwrap();
 
-        assert_eq!(wire_keyset.id, domain_keyset.id);
-        assert_eq!(original_keyset.keys, domain_keyset.keys);
+        assert_eq!(wire_keyset.keyset_id, domain_keyset.keyset_id);
+        assert_eq!(original_keyset.items, domain_keyset.items);
+    }
+
+    #[test]
+    fn test_sv2_keyset_cdk_conversion() {
+        let original_keyset = get_random_keyset();
+        let cdk_keyset = KeySet::try_from(original_keyset.clone()).unwrap();
+        let domain_keyset = Sv2KeySet::try_from(cdk_keyset).unwrap();
+
+        assert_eq!(original_keyset, domain_keyset);
     }
 
     #[test]
     fn test_sv2_blind_sig_set_domain_wire_conversion() {
         let original_sigset = get_random_sigset();
-        let wire_sigset: Sv2BlindSignatureSet = original_sigset.clone().into();
-        let domain_sigset: Sv2BlindSignatureSet = wire_sigset.clone().try_into().unwrap();
+        let wire_sigset: Sv2BlindSignatureSetWire = original_sigset.clone().into();
+        let domain_sigset: BlindSignatureSet = wire_sigset.clone().try_into().unwrap();
 
         assert_eq!(wire_sigset.keyset_id, domain_sigset.keyset_id);
-        assert_eq!(original_sigset.signatures, domain_sigset.signatures);
+        assert_eq!(original_sigset.items, domain_sigset.items);
+    }
+
+    #[test]
+    fn test_sv2_blind_sig_set_rejects_invalid_dleq() {
+        let wire_sigset: Sv2BlindSignatureSetWire = get_random_sigset().into();
+        let mut raw = wire_sigset.encoded_data.inner_as_ref().to_vec();
+        // the e scalar of the first signature, above the curve order
+        raw[33..65].copy_from_slice(&[0xff; 32]);
+        let wire_sigset = Sv2BlindSignatureSetWire {
+            keyset_id: wire_sigset.keyset_id,
+            encoded_data: raw.try_into().unwrap(),
+        };
+        assert!(BlindSignatureSet::try_from(wire_sigset).is_err());
     }
 
     #[test]
     fn test_sv2_blinded_msg_set_domain_wire_conversion() {
         let original_msgset = get_random_msgset();
         let wire_msgset: Sv2BlindedMessageSetWire = original_msgset.clone().into();
-        let domain_msgset: Sv2BlindedMessageSet = wire_msgset.clone().try_into().unwrap();
+        let domain_msgset: BlindedMessageSet = wire_msgset.clone().try_into().unwrap();
 
         assert_eq!(wire_msgset.keyset_id, domain_msgset.keyset_id);
         assert_eq!(original_msgset.items, domain_msgset.items);
     }
-}
\ No newline at end of file
+}
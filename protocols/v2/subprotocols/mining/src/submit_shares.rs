@@ -133,6 +133,21 @@ impl<'a> SubmitSharesError<'a> {
     pub fn invalid_job_id_error_code() -> &'static str {
         "invalid-job-id"
     }
+    pub fn duplicate_share_error_code() -> &'static str {
+        "duplicate-share"
+    }
+    pub fn invalid_blinded_messages_error_code() -> &'static str {
+        "invalid-blinded-messages"
+    }
+    pub fn ehash_diverted_error_code() -> &'static str {
+        "ehash-diverted"
+    }
+    pub fn ehash_amount_too_large_error_code() -> &'static str {
+        "ehash-amount-too-large"
+    }
+    pub fn ehash_above_share_work_error_code() -> &'static str {
+        "ehash-above-share-work"
+    }
 }
 #[cfg(feature = "with_serde")]
 use binary_sv2::GetSize;
@@ -0,0 +1,414 @@
+use bitcoin::hex::{DisplayHex, FromHex};
+use mining_sv2::cashu::Sv2BlindedMessageSetWire;
+use serde::Deserialize;
+use std::{
+    collections::VecDeque,
+    fmt, fs, io,
+    path::Path,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+/// Settings for the ehash issuance circuit breaker. When the accepted-share rate of a downstream
+/// jumps above `spike_multiplier` times its trailing average, its blinded messages are diverted
+/// to the review queue instead of being signed by the mint and the share is answered with an
+/// `ehash-diverted` error. The operator approves or rejects them through the review file.
+/// Disabled by default.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct CircuitBreakerConfig {
+    pub enabled: bool,
+    /// Multiple of the trailing average share rate that trips the breaker
+    pub spike_multiplier: f64,
+    /// Length in seconds of a share-rate sampling window
+    pub window_secs: u64,
+    /// Number of past windows averaged into the trailing share rate
+    pub trailing_windows: usize,
+    /// Seconds a tripped downstream stays diverted before issuance resumes
+    pub cooldown_secs: u64,
+    /// Maximum number of diverted issuances kept for review, oldest are dropped first
+    pub review_queue_capacity: usize,
+    /// File of [`ReviewDecision`]s applied to the review queue on SIGUSR1, the queue is only
+    /// logged when unset
+    pub review_file: Option<String>,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            spike_multiplier: 10.0,
+            window_secs: 60,
+            trailing_windows: 10,
+            cooldown_secs: 600,
+            review_queue_capacity: 10_000,
+            review_file: None,
+        }
+    }
+}
+
+/// Outcome of recording an accepted share in a [`ShareRateMonitor`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Issuance {
+    /// The blinded messages can be signed by the mint
+    Allowed,
+    /// This share tripped the breaker, its issuance is diverted
+    Tripped,
+    /// The breaker was already tripped, issuance is diverted
+    Diverted,
+}
+
+/// Tracks the accepted-share rate of a single downstream in fixed windows.
+#[derive(Debug)]
+pub struct ShareRateMonitor {
+    config: CircuitBreakerConfig,
+    window_start: Instant,
+    current_window: u64,
+    trailing: VecDeque<u64>,
+    tripped_until: Option<Instant>,
+}
+
+impl ShareRateMonitor {
+    pub fn new(config: CircuitBreakerConfig, now: Instant) -> Self {
+        Self {
+            config,
+            window_start: now,
+            current_window: 0,
+            trailing: VecDeque::new(),
+            tripped_until: None,
+        }
+    }
+
+    /// Records an accepted share and returns whether its issuance must be diverted. Shares
+    /// arriving while the breaker is tripped are not counted, so that a spike that outlasts the
+    /// cooldown trips the breaker again instead of inflating the trailing average.
+    pub fn on_accepted_share(&mut self, now: Instant) -> Issuance {
+        if !self.config.enabled {
+            return Issuance::Allowed;
+        }
+        if let Some(until) = self.tripped_until {
+            if now < until {
+                return Issuance::Diverted;
+            }
+            self.tripped_until = None;
+            self.window_start = now;
+            self.current_window = 0;
+        }
+        self.roll_windows(now);
+        self.current_window += 1;
+
+        // no baseline yet, the trailing average needs full history to be meaningful
+        if self.trailing.len() < self.config.trailing_windows {
+            return Issuance::Allowed;
+        }
+        let average = self.trailing_average().max(1.0);
+        if self.current_window as f64 > average * self.config.spike_multiplier {
+            self.tripped_until = Some(now + Duration::from_secs(self.config.cooldown_secs));
+            return Issuance::Tripped;
+        }
+        Issuance::Allowed
+    }
+
+    pub fn current_window_shares(&self) -> u64 {
+        self.current_window
+    }
+
+    pub fn trailing_average(&self) -> f64 {
+        if self.trailing.is_empty() {
+            return 0.0;
+        }
+        self.trailing.iter().sum::<u64>() as f64 / self.trailing.len() as f64
+    }
+
+    fn roll_windows(&mut self, now: Instant) {
+        let window = Duration::from_secs(self.config.window_secs.max(1));
+        let mut closed = 0;
+        while now.duration_since(self.window_start) >= window {
+            // an idle period only needs enough empty windows to fill the history
+            if closed <= self.config.trailing_windows {
+                self.push_window(self.current_window);
+            }
+            self.current_window = 0;
+            self.window_start += window;
+            closed += 1;
+        }
+    }
+
+    fn push_window(&mut self, shares: u64) {
+        self.trailing.push_back(shares);
+        while self.trailing.len() > self.config.trailing_windows {
+            self.trailing.pop_front();
+        }
+    }
+}
+
+/// Issuance withheld by the circuit breaker, kept until an operator reviews it.
+#[derive(Debug, Clone)]
+pub struct DivertedIssuance {
+    pub downstream_id: u32,
+    pub channel_id: u32,
+    pub sequence_number: u32,
+    pub hash: [u8; 32],
+    pub blinded_messages: Sv2BlindedMessageSetWire<'static>,
+}
+
+impl fmt::Display for DivertedIssuance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "downstream {} channel {} sequence {} share {}",
+            self.downstream_id,
+            self.channel_id,
+            self.sequence_number,
+            self.hash.to_lower_hex_string()
+        )
+    }
+}
+
+/// Diverted issuances a [`ReviewDecision`] applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReviewTarget {
+    All,
+    Share([u8; 32]),
+}
+
+/// Operator decision on diverted issuances, written to the review file one per line as
+/// `approve <share hash>` or `reject <share hash>`, `all` standing for every queued issuance.
+/// Approved issuances are signed and sent to their downstream, rejected ones are discarded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReviewDecision {
+    pub approve: bool,
+    pub target: ReviewTarget,
+}
+
+impl FromStr for ReviewDecision {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let approve = match words.next() {
+            Some("approve") => true,
+            Some("reject") => false,
+            _ => return Err(format!("expected approve or reject: {}", s)),
+        };
+        let target = match words.next() {
+            Some("all") => ReviewTarget::All,
+            Some(hash) => {
+                let hash = <[u8; 32]>::from_hex(hash)
+                    .map_err(|_| format!("invalid share hash: {}", hash))?;
+                ReviewTarget::Share(hash)
+            }
+            None => return Err(format!("missing share hash: {}", s)),
+        };
+        if words.next().is_some() {
+            return Err(format!("unexpected trailing words: {}", s));
+        }
+        Ok(Self { approve, target })
+    }
+}
+
+/// Reads the decisions of the review file at `path` and empties it so they are only applied
+/// once. Blank lines and lines starting with `#` are skipped, a missing file holds no decision.
+/// Nothing is taken from a file with an invalid line.
+pub fn take_review_decisions(path: impl AsRef<Path>) -> io::Result<Vec<ReviewDecision>> {
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+    let decisions = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            line.parse()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    fs::write(&path, "")?;
+    Ok(decisions)
+}
+
+/// Bounded queue of diverted issuances shared by all downstreams of the pool.
+#[derive(Debug)]
+pub struct ReviewQueue {
+    capacity: usize,
+    items: VecDeque<DivertedIssuance>,
+}
+
+impl ReviewQueue {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            items: VecDeque::new(),
+        }
+    }
+
+    /// Adds an issuance to the queue, returning the one evicted to make room if the queue is
+    /// full.
+    pub fn push(&mut self, item: DivertedIssuance) -> Option<DivertedIssuance> {
+        let evicted = if self.items.len() >= self.capacity {
+            self.items.pop_front()
+        } else {
+            None
+        };
+        self.items.push_back(item);
+        evicted
+    }
+
+    /// Removes and returns the queued issuances `target` applies to, oldest first.
+    pub fn take(&mut self, target: ReviewTarget) -> Vec<DivertedIssuance> {
+        let (taken, kept) = self.items.drain(..).partition(|item| match target {
+            ReviewTarget::All => true,
+            ReviewTarget::Share(hash) => item.hash == hash,
+        });
+        self.items = kept;
+        taken
+    }
+
+    /// Queued issuances, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &DivertedIssuance> {
+        self.items.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config() -> CircuitBreakerConfig {
+        CircuitBreakerConfig {
+            enabled: true,
+            spike_multiplier: 3.0,
+            window_secs: 10,
+            trailing_windows: 3,
+            cooldown_secs: 60,
+            review_queue_capacity: 2,
+            review_file: None,
+        }
+    }
+
+    #[test]
+    fn trips_on_spike_and_recovers_after_cooldown() {
+        let start = Instant::now();
+        let mut monitor = ShareRateMonitor::new(config(), start);
+
+        // three windows of 2 shares build a trailing average of 2
+        for window in 0..3 {
+            for _ in 0..2 {
+                let now = start + Duration::from_secs(window * 10);
+                assert_eq!(monitor.on_accepted_share(now), Issuance::Allowed);
+            }
+        }
+
+        // the spike window exceeds 3 * 2 shares on the 7th share
+        let spike = start + Duration::from_secs(30);
+        for _ in 0..6 {
+            assert_eq!(monitor.on_accepted_share(spike), Issuance::Allowed);
+        }
+        assert_eq!(monitor.on_accepted_share(spike), Issuance::Tripped);
+        assert_eq!(
+            monitor.on_accepted_share(spike + Duration::from_secs(59)),
+            Issuance::Diverted
+        );
+
+        let after_cooldown = spike + Duration::from_secs(60);
+        assert_eq!(monitor.on_accepted_share(after_cooldown), Issuance::Allowed);
+        assert_eq!(monitor.current_window_shares(), 1);
+    }
+
+    #[test]
+    fn does_not_trip_without_baseline() {
+        let start = Instant::now();
+        let mut monitor = ShareRateMonitor::new(config(), start);
+        for _ in 0..100 {
+            assert_eq!(monitor.on_accepted_share(start), Issuance::Allowed);
+        }
+    }
+
+    #[test]
+    fn review_queue_evicts_oldest() {
+        let mut queue = ReviewQueue::new(2);
+        let item = |sequence_number| DivertedIssuance {
+            downstream_id: 0,
+            channel_id: 1,
+            sequence_number,
+            hash: [sequence_number as u8; 32],
+            blinded_messages: Sv2BlindedMessageSetWire::default(),
+        };
+        assert!(queue.push(item(0)).is_none());
+        assert!(queue.push(item(1)).is_none());
+        assert_eq!(queue.push(item(2)).unwrap().sequence_number, 0);
+        assert_eq!(queue.len(), 2);
+
+        let taken = queue.take(ReviewTarget::Share([2; 32]));
+        assert_eq!(taken.len(), 1);
+        assert_eq!(taken[0].sequence_number, 2);
+        assert!(queue.take(ReviewTarget::Share([2; 32])).is_empty());
+        assert_eq!(queue.len(), 1);
+
+        let taken: Vec<_> = queue
+            .take(ReviewTarget::All)
+            .iter()
+            .map(|i| i.sequence_number)
+            .collect();
+        assert_eq!(taken, vec![1]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn takes_review_decisions_once() {
+        let path = std::env::temp_dir().join(format!("review-{}.txt", std::process::id()));
+        std::fs::write(
+            &path,
+            format!("# checked\napprove {}\n\nreject all\n", "02".repeat(32)),
+        )
+        .unwrap();
+        let decisions = take_review_decisions(&path).unwrap();
+        assert_eq!(decisions.len(), 2);
+        assert_eq!(decisions[0].target, ReviewTarget::Share([2; 32]));
+        assert!(take_review_decisions(&path).unwrap().is_empty());
+
+        // a bad line leaves the file untouched
+        std::fs::write(&path, "approve all\napprove\n").unwrap();
+        assert!(take_review_decisions(&path).is_err());
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "approve all\napprove\n"
+        );
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(take_review_decisions(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn parses_review_decisions() {
+        let hash = "01".repeat(32);
+        assert_eq!(
+            format!("approve {}", hash).parse::<ReviewDecision>(),
+            Ok(ReviewDecision {
+                approve: true,
+                target: ReviewTarget::Share([1; 32])
+            })
+        );
+        assert_eq!(
+            " reject  all ".parse::<ReviewDecision>(),
+            Ok(ReviewDecision {
+                approve: false,
+                target: ReviewTarget::All
+            })
+        );
+        assert!("approve".parse::<ReviewDecision>().is_err());
+        assert!("approve 0102".parse::<ReviewDecision>().is_err());
+        assert!("sign all".parse::<ReviewDecision>().is_err());
+        assert!("reject all now".parse::<ReviewDecision>().is_err());
+    }
+}
@@ -0,0 +1,152 @@
+use roles_logic_sv2::utils::Mutex;
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tracing::error;
+
+/// Limits applied per source address when a downstream connects.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ConnectionLimitConfig {
+    /// Simultaneous connections per address, 0 disables the limit
+    pub max_per_ip: usize,
+    /// Connection attempts per address over the last minute, 0 disables the limit
+    pub max_attempts_per_ip_per_minute: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitExceeded {
+    TooManyConnections,
+    TooManyAttempts,
+}
+
+/// Open connections and recent connection attempts of every source address.
+#[derive(Debug)]
+pub struct ConnectionLimiter {
+    config: ConnectionLimitConfig,
+    open: HashMap<IpAddr, usize>,
+    attempts: HashMap<IpAddr, VecDeque<Instant>>,
+}
+
+impl ConnectionLimiter {
+    pub fn new(config: ConnectionLimitConfig) -> Self {
+        Self {
+            config,
+            open: HashMap::new(),
+            attempts: HashMap::new(),
+        }
+    }
+
+    /// Records a connection attempt from `ip` and opens a connection for it unless one of the
+    /// limits is reached. The connection stays counted until the returned slot is dropped.
+    pub fn acquire(
+        self_: &Arc<Mutex<Self>>,
+        ip: IpAddr,
+        now: Instant,
+    ) -> Result<ConnectionSlot, LimitExceeded> {
+        let res = self_.safe_lock(|l| l.try_open(ip, now));
+        match res {
+            Ok(Ok(())) => Ok(ConnectionSlot {
+                limiter: self_.clone(),
+                ip,
+            }),
+            Ok(Err(e)) => Err(e),
+            Err(e) => {
+                // a poisoned limiter must not lock every miner out
+                error!("Failed to lock connection limiter: {}", e);
+                Ok(ConnectionSlot {
+                    limiter: self_.clone(),
+                    ip,
+                })
+            }
+        }
+    }
+
+    fn try_open(&mut self, ip: IpAddr, now: Instant) -> Result<(), LimitExceeded> {
+        if self.config.max_attempts_per_ip_per_minute > 0 {
+            self.attempts.retain(|_, attempts| {
+                while let Some(at) = attempts.front() {
+                    if now.saturating_duration_since(*at) < Duration::from_secs(60) {
+                        break;
+                    }
+                    attempts.pop_front();
+                }
+                !attempts.is_empty()
+            });
+            let attempts = self.attempts.entry(ip).or_default();
+            attempts.push_back(now);
+            if attempts.len() > self.config.max_attempts_per_ip_per_minute {
+                return Err(LimitExceeded::TooManyAttempts);
+            }
+        }
+        let open = self.open.entry(ip).or_insert(0);
+        if self.config.max_per_ip > 0 && *open >= self.config.max_per_ip {
+            return Err(LimitExceeded::TooManyConnections);
+        }
+        *open += 1;
+        Ok(())
+    }
+
+    fn release(&mut self, ip: IpAddr) {
+        if let Some(open) = self.open.get_mut(&ip) {
+            *open = open.saturating_sub(1);
+            if *open == 0 {
+                self.open.remove(&ip);
+            }
+        }
+    }
+}
+
+/// Connection counted against the limits of its source address until dropped.
+#[derive(Debug)]
+pub struct ConnectionSlot {
+    limiter: Arc<Mutex<ConnectionLimiter>>,
+    ip: IpAddr,
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        let ip = self.ip;
+        if let Err(e) = self.limiter.safe_lock(|l| l.release(ip)) {
+            error!("Failed to lock connection limiter: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn limits_connections_and_attempts_per_ip() {
+        let start = Instant::now();
+        let limiter = Arc::new(Mutex::new(ConnectionLimiter::new(ConnectionLimitConfig {
+            max_per_ip: 2,
+            max_attempts_per_ip_per_minute: 4,
+        })));
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+
+        let first = ConnectionLimiter::acquire(&limiter, ip, start).unwrap();
+        let _second = ConnectionLimiter::acquire(&limiter, ip, start).unwrap();
+        assert_eq!(
+            ConnectionLimiter::acquire(&limiter, ip, start).unwrap_err(),
+            LimitExceeded::TooManyConnections
+        );
+        assert!(ConnectionLimiter::acquire(&limiter, other, start).is_ok());
+
+        // closing a connection frees its slot, but the 5th attempt within a minute is refused
+        drop(first);
+        let third = ConnectionLimiter::acquire(&limiter, ip, start).unwrap();
+        drop(third);
+        assert_eq!(
+            ConnectionLimiter::acquire(&limiter, ip, start).unwrap_err(),
+            LimitExceeded::TooManyAttempts
+        );
+        assert!(ConnectionLimiter::acquire(&limiter, ip, start + Duration::from_secs(60)).is_ok());
+    }
+}
@@ -0,0 +1,134 @@
+use bitcoin::hex::DisplayHex;
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Block solved by one of the pool's downstreams.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FoundBlock {
+    pub timestamp: u64,
+    /// Block hash in the usual big-endian display order
+    pub hash: [u8; 32],
+    pub height: Option<u64>,
+    pub template_id: u64,
+    pub downstream_id: u32,
+    pub channel_id: u32,
+}
+
+impl FoundBlock {
+    pub fn new(
+        hash: [u8; 32],
+        coinbase: &[u8],
+        template_id: u64,
+        downstream_id: u32,
+        channel_id: u32,
+    ) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        Self {
+            timestamp,
+            hash,
+            height: bip34_height(coinbase),
+            template_id,
+            downstream_id,
+            channel_id,
+        }
+    }
+}
+
+/// Reads the block height that BIP34 requires as the first push of the coinbase script.
+fn bip34_height(coinbase: &[u8]) -> Option<u64> {
+    // version
+    let mut offset = 4;
+    // segwit marker and flag
+    if coinbase.get(offset..offset + 2) == Some(&[0x00, 0x01][..]) {
+        offset += 2;
+    }
+    // single input count, null prevout, then a script shorter than 0xfd bytes
+    offset += 1 + 36 + 1;
+    script_height(coinbase.get(offset..)?)
+}
+
+/// Reads the BIP34 height pushed at the start of a coinbase script.
+pub fn script_height(script: &[u8]) -> Option<u64> {
+    let push_len = *script.first()? as usize;
+    if push_len == 0 || push_len > 8 {
+        return None;
+    }
+    let bytes = script.get(1..1 + push_len)?;
+    let mut height = [0u8; 8];
+    height[..push_len].copy_from_slice(bytes);
+    Some(u64::from_le_bytes(height))
+}
+
+/// Counts the blocks found by the pool and appends each of them to a file, so finds survive a
+/// pool restart.
+#[derive(Debug, Default)]
+pub struct FoundBlocks {
+    file: Option<File>,
+    count: u64,
+}
+
+impl FoundBlocks {
+    pub fn new<P: AsRef<Path>>(path: Option<P>) -> io::Result<Self> {
+        let file = match path {
+            Some(path) => Some(OpenOptions::new().create(true).append(true).open(path)?),
+            None => None,
+        };
+        Ok(Self { file, count: 0 })
+    }
+
+    pub fn record(&mut self, block: &FoundBlock) -> io::Result<()> {
+        self.count += 1;
+        match self.file.as_mut() {
+            Some(file) => {
+                let height = block
+                    .height
+                    .map(|h| h.to_string())
+                    .unwrap_or_else(|| "-".to_string());
+                writeln!(
+                    file,
+                    "{} {} {} {} {} {}",
+                    block.timestamp,
+                    block.hash.to_lower_hex_string(),
+                    height,
+                    block.template_id,
+                    block.downstream_id,
+                    block.channel_id
+                )
+                .and_then(|_| file.flush())
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reads_bip34_height_from_coinbase() {
+        let mut coinbase = vec![2, 0, 0, 0, 0x00, 0x01, 1];
+        coinbase.extend_from_slice(&[0; 32]);
+        coinbase.extend_from_slice(&[0xff; 4]);
+        // script of 5 bytes pushing height 840000 (0x0cd140)
+        coinbase.extend_from_slice(&[5, 3, 0x40, 0xd1, 0x0c, 0]);
+        assert_eq!(bip34_height(&coinbase), Some(840_000));
+
+        // same transaction without the segwit marker
+        coinbase.drain(4..6);
+        assert_eq!(bip34_height(&coinbase), Some(840_000));
+
+        assert_eq!(bip34_height(&coinbase[..10]), None);
+    }
+}
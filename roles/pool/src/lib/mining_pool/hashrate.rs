@@ -0,0 +1,89 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// Estimates the hashrate of a channel from the work of the shares it got accepted over a
+/// sliding window, instead of trusting the nominal hashrate it advertised.
+#[derive(Debug)]
+pub struct HashrateEstimator {
+    window: Duration,
+    opened_at: Instant,
+    /// Accepted shares and the number of hashes each of them is expected to have taken
+    shares: VecDeque<(Instant, f64)>,
+}
+
+impl HashrateEstimator {
+    pub fn new(window: Duration, now: Instant) -> Self {
+        Self {
+            window,
+            opened_at: now,
+            shares: VecDeque::new(),
+        }
+    }
+
+    pub fn on_accepted_share(&mut self, work: f64, now: Instant) {
+        self.prune(now);
+        self.shares.push_back((now, work));
+    }
+
+    /// Hashes per second over the window, or since the channel was opened when it is younger
+    /// than the window.
+    pub fn estimate(&mut self, now: Instant) -> f64 {
+        self.prune(now);
+        let elapsed = now
+            .saturating_duration_since(self.opened_at)
+            .min(self.window)
+            .as_secs_f64();
+        if elapsed <= 0.0 {
+            return 0.0;
+        }
+        self.shares.iter().map(|(_, work)| work).sum::<f64>() / elapsed
+    }
+
+    fn prune(&mut self, now: Instant) {
+        while let Some((at, _)) = self.shares.front() {
+            if now.saturating_duration_since(*at) <= self.window {
+                break;
+            }
+            self.shares.pop_front();
+        }
+    }
+}
+
+/// Formats a hashrate in hashes per second with a unit prefix, e.g. `1.50 Th/s`.
+pub fn format_hashrate(hashrate: f64) -> String {
+    const UNITS: [&str; 7] = ["h/s", "Kh/s", "Mh/s", "Gh/s", "Th/s", "Ph/s", "Eh/s"];
+    let mut value = hashrate;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+    format!("{:.2} {}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn estimates_over_a_sliding_window() {
+        let start = Instant::now();
+        let mut estimator = HashrateEstimator::new(Duration::from_secs(600), start);
+
+        // a share worth 6e12 hashes every minute is 1e11 h/s, the window holds the last 10
+        for minute in 1..=20 {
+            estimator.on_accepted_share(6e12, start + Duration::from_secs(minute * 60));
+        }
+        let estimate = estimator.estimate(start + Duration::from_secs(20 * 60 + 30));
+        assert!((estimate - 1e11).abs() < 1.0, "{}", estimate);
+
+        // shares older than the window are forgotten
+        let estimate = estimator.estimate(start + Duration::from_secs(40 * 60));
+        assert_eq!(estimate, 0.0);
+
+        assert_eq!(format_hashrate(1.5e12), "1.50 Th/s");
+        assert_eq!(format_hashrate(12.0), "12.00 h/s");
+    }
+}
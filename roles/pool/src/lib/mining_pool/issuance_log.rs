@@ -0,0 +1,152 @@
+use bitcoin::{
+    hashes::{sha256, Hash},
+    hex::{DisplayHex, FromHex},
+};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Chain hash preceding the first entry of a log.
+const GENESIS: [u8; 32] = [0; 32];
+
+/// Ehash issued by the mint for a single share.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IssuanceRecord {
+    pub timestamp: u64,
+    pub downstream_id: u32,
+    pub channel_id: u32,
+    pub share_hash: [u8; 32],
+    pub keyset_id: u64,
+    pub amount: u64,
+}
+
+impl IssuanceRecord {
+    pub fn now(
+        downstream_id: u32,
+        channel_id: u32,
+        share_hash: [u8; 32],
+        keyset_id: u64,
+        amount: u64,
+    ) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        Self {
+            timestamp,
+            downstream_id,
+            channel_id,
+            share_hash,
+            keyset_id,
+            amount,
+        }
+    }
+
+    fn fields(&self) -> String {
+        format!(
+            "{} {} {} {} {:016x} {}",
+            self.timestamp,
+            self.downstream_id,
+            self.channel_id,
+            self.share_hash.to_lower_hex_string(),
+            self.keyset_id,
+            self.amount
+        )
+    }
+}
+
+fn chain_hash(prev: &[u8; 32], fields: &str) -> [u8; 32] {
+    let mut preimage = prev.to_vec();
+    preimage.extend_from_slice(fields.as_bytes());
+    sha256::Hash::hash(&preimage).to_byte_array()
+}
+
+/// Splits a log line into its record fields and the chain hash stored at the end of it.
+fn parse_line(line: &str) -> io::Result<(&str, [u8; 32])> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("bad entry: {}", line));
+    let (fields, hash) = line.rsplit_once(' ').ok_or_else(invalid)?;
+    let hash = <[u8; 32]>::from_hex(hash).map_err(|_| invalid())?;
+    Ok((fields, hash))
+}
+
+/// Append-only log of the ehash issued by the mint, kept apart from the mint database so
+/// issuance can be audited on its own. Each line holds the record fields followed by
+/// `sha256(previous chain hash || fields)`, so removing or editing an entry breaks every
+/// hash after it.
+#[derive(Debug)]
+pub struct IssuanceLog {
+    file: File,
+    last_hash: [u8; 32],
+}
+
+impl IssuanceLog {
+    /// Opens the log at `path`, creating it if needed, after checking the existing chain.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let (_, last_hash) = Self::verify(&path).or_else(|e| match e.kind() {
+            io::ErrorKind::NotFound => Ok((0, GENESIS)),
+            _ => Err(e),
+        })?;
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { file, last_hash })
+    }
+
+    pub fn append(&mut self, record: &IssuanceRecord) -> io::Result<()> {
+        let fields = record.fields();
+        let hash = chain_hash(&self.last_hash, &fields);
+        writeln!(self.file, "{} {}", fields, hash.to_lower_hex_string())?;
+        self.file.flush()?;
+        self.last_hash = hash;
+        Ok(())
+    }
+
+    /// Recomputes the chain of the log at `path`, returning the number of entries and the last
+    /// chain hash.
+    pub fn verify<P: AsRef<Path>>(path: P) -> io::Result<(usize, [u8; 32])> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut last_hash = GENESIS;
+        let mut entries = 0;
+        for line in reader.lines() {
+            let line = line?;
+            let (fields, hash) = parse_line(&line)?;
+            if chain_hash(&last_hash, fields) != hash {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("chain broken at entry {}", entries),
+                ));
+            }
+            last_hash = hash;
+            entries += 1;
+        }
+        Ok((entries, last_hash))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn chain_survives_reopen_and_detects_tampering() {
+        let path = std::env::temp_dir().join(format!("issuance-log-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let record = |amount: u64| {
+            IssuanceRecord::now(1, 2, [amount as u8; 32], 0x00ad268c4d1f5826, amount)
+        };
+        let mut log = IssuanceLog::open(&path).unwrap();
+        log.append(&record(8)).unwrap();
+        drop(log);
+        let mut log = IssuanceLog::open(&path).unwrap();
+        log.append(&record(16)).unwrap();
+        assert_eq!(IssuanceLog::verify(&path).unwrap().0, 2);
+
+        let tampered = std::fs::read_to_string(&path).unwrap().replacen(" 8 ", " 9 ", 1);
+        std::fs::write(&path, tampered).unwrap();
+        assert!(IssuanceLog::verify(&path).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
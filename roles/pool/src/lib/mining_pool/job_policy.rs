@@ -0,0 +1,149 @@
+use roles_logic_sv2::{job_creator::tx_outputs_to_costum_scripts, mining_sv2::SetCustomMiningJob};
+use super::found_blocks::script_height;
+use serde::Deserialize;
+use std::fmt;
+use stratum_common::bitcoin::TxOut;
+
+/// Checks applied to the jobs declared with `SetCustomMiningJob` before they are acknowledged.
+/// Every check is disabled by default.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct CustomJobPolicyConfig {
+    /// Every pool coinbase output script has to be paid by the declared coinbase
+    pub require_pool_outputs: bool,
+    /// Smallest sum of transaction fees in sats, the coinbase value minus the block subsidy at
+    /// the BIP34 height of the job
+    pub min_fees: u64,
+    /// Blocks between two subsidy halvings, 150 on regtest
+    pub halving_interval: u64,
+    /// Largest age of the job `min_ntime` in seconds, 0 disables the check
+    pub max_template_age_secs: u64,
+}
+
+impl Default for CustomJobPolicyConfig {
+    fn default() -> Self {
+        Self {
+            require_pool_outputs: false,
+            min_fees: 0,
+            halving_interval: 210_000,
+            max_template_age_secs: 0,
+        }
+    }
+}
+
+/// Reason a declared job was refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejection {
+    /// Job field the policy failed on
+    pub field: &'static str,
+    pub reason: String,
+}
+
+impl Rejection {
+    /// Error code sent back in `SetCustomMiningJobError`.
+    pub fn error_code(&self) -> String {
+        format!("invalid-job-param-value-{}", self.field)
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.reason)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CustomJobPolicy {
+    config: CustomJobPolicyConfig,
+    pool_outputs: Vec<TxOut>,
+}
+
+impl CustomJobPolicy {
+    pub fn new(config: CustomJobPolicyConfig, pool_outputs: Vec<TxOut>) -> Self {
+        Self {
+            config,
+            pool_outputs,
+        }
+    }
+
+    /// Checks `job` against the policy, `now` being the current unix time in seconds.
+    pub fn check(&self, job: &SetCustomMiningJob, now: u64) -> Result<(), Rejection> {
+        if self.config.require_pool_outputs {
+            let declared = tx_outputs_to_costum_scripts(&job.coinbase_tx_outputs.to_vec());
+            let missing = self.pool_outputs.iter().find(|required| {
+                !declared
+                    .iter()
+                    .any(|output| output.script_pubkey == required.script_pubkey)
+            });
+            if let Some(missing) = missing {
+                return Err(Rejection {
+                    field: "coinbase_tx_outputs",
+                    reason: format!("pool output {:x} is not paid", missing.script_pubkey),
+                });
+            }
+        }
+
+        if self.config.min_fees > 0 {
+            let fees = fees(
+                &job.coinbase_prefix.to_vec(),
+                job.coinbase_tx_value_remaining,
+                self.config.halving_interval,
+            )
+            .ok_or_else(|| Rejection {
+                field: "coinbase_prefix",
+                reason: "no BIP34 height".to_string(),
+            })?;
+            if fees < self.config.min_fees {
+                return Err(Rejection {
+                    field: "coinbase_tx_value_remaining",
+                    reason: format!(
+                        "{} sats of fees, below the minimum of {}",
+                        fees, self.config.min_fees
+                    ),
+                });
+            }
+        }
+
+        if self.config.max_template_age_secs > 0 {
+            let age = now.saturating_sub(job.min_ntime as u64);
+            if age > self.config.max_template_age_secs {
+                return Err(Rejection {
+                    field: "min_ntime",
+                    reason: format!(
+                        "template is {}s old, above the maximum of {}s",
+                        age, self.config.max_template_age_secs
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Transaction fees paid by a coinbase whose script starts with `coinbase_prefix` and which
+/// pays out `value` sats, or `None` when the prefix does not start with a BIP34 height.
+fn fees(coinbase_prefix: &[u8], value: u64, halving_interval: u64) -> Option<u64> {
+    let halvings = script_height(coinbase_prefix)? / halving_interval.max(1);
+    let subsidy = match halvings {
+        0..=63 => 5_000_000_000 >> halvings,
+        _ => 0,
+    };
+    Some(value.saturating_sub(subsidy))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fees_subtract_the_subsidy_at_the_bip34_height() {
+        // height 840000 (0x0cd140), the fourth halving
+        let prefix = [3, 0x40, 0xd1, 0x0c, 0xff];
+        assert_eq!(fees(&prefix, 312_500_000 + 1_000, 210_000), Some(1_000));
+        assert_eq!(fees(&prefix, 100, 210_000), Some(0));
+        // regtest halves every 150 blocks, 64 halvings leave no subsidy
+        assert_eq!(fees(&prefix, 1_000, 150), Some(1_000));
+        assert_eq!(fees(&[0], 1_000, 210_000), None);
+        assert_eq!(fees(&[3, 0x40], 1_000, 210_000), None);
+    }
+}
@@ -1,5 +1,16 @@
-use super::super::mining_pool::Downstream;
+use super::super::mining_pool::{
+    circuit_breaker::{DivertedIssuance, Issuance},
+    found_blocks::FoundBlock,
+    hashrate::HashrateEstimator,
+    issuance_log::IssuanceRecord,
+    misbehavior::Offense,
+    share_cache::{RecentShares, ShareKey},
+    share_log::{ShareOutcome, ShareRecord},
+    vardiff::ChannelVardiff,
+    Downstream,
+};
 use cashu::{BlindSignatureSet, BlindedMessageSet, Sv2BlindSignatureSetWire, Sv2BlindedMessageSetWire};
+use bitcoin::hex::DisplayHex;
 use cdk::{mint::Mint, nuts::BlindSignature};
 use roles_logic_sv2::{
     errors::Error,
@@ -11,8 +22,12 @@ use roles_logic_sv2::{
     template_distribution_sv2::SubmitSolution,
     utils::Mutex,
 };
-use std::{convert::{TryFrom, TryInto}, sync::Arc};
-use tracing::error;
+use std::{
+    convert::{TryFrom, TryInto},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tracing::{debug, error, field, info, info_span, warn, Span};
 
 impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting> for Downstream {
     fn get_channel_type(&self) -> SupportedChannelTypes {
@@ -42,7 +57,7 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
             .safe_lock(|factory| {
                 match factory.add_standard_channel(
                     incoming.request_id.as_u32(),
-                    incoming.nominal_hash_rate,
+                    incoming.nominal_hash_rate.max(self.fixed_minimum_hashrate),
                     header_only,
                     self.id,
                 ) {
@@ -59,6 +74,9 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
             .map_err(|e| roles_logic_sv2::Error::PoisonLock(e.to_string()))??;
         let mut result = vec![];
         for response in reposnses {
+            if let Mining::OpenStandardMiningChannelSuccess(success) = &response {
+                self.channel_ids.push(success.channel_id);
+            }
             result.push(SendTo::Respond(response.into_static()))
         }
         Ok(SendTo::Multiple(result))
@@ -69,7 +87,7 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
         m: OpenExtendedMiningChannel,
     ) -> Result<SendTo<()>, Error> {
         let request_id = m.request_id;
-        let hash_rate = m.nominal_hash_rate;
+        let hash_rate = m.nominal_hash_rate.max(self.fixed_minimum_hashrate);
         let min_extranonce_size = m.min_extranonce_size;
         let messages_res = self
             .channel_factory
@@ -77,6 +95,12 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
             .map_err(|e| roles_logic_sv2::Error::PoisonLock(e.to_string()))?;
         match messages_res {
             Ok(messages) => {
+                for message in &messages {
+                    if let Mining::OpenExtendedMiningChannelSuccess(success) = message {
+                        self.channel_ids.push(success.channel_id);
+                        self.track_channel(success.channel_id, hash_rate);
+                    }
+                }
                 let messages = messages.into_iter().map(SendTo::Respond).collect();
                 Ok(SendTo::Multiple(messages))
             }
@@ -85,8 +109,14 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
     }
 
     fn handle_update_channel(&mut self, m: UpdateChannel) -> Result<SendTo<()>, Error> {
-        let maximum_target =
-            roles_logic_sv2::utils::hash_rate_to_target(m.nominal_hash_rate.into(), 10.0)?;
+        let hash_rate = m.nominal_hash_rate.max(self.fixed_minimum_hashrate);
+        if let Some(vardiff) = self.vardiff.get_mut(&m.channel_id) {
+            vardiff.reset(hash_rate, Instant::now());
+        }
+        let maximum_target = roles_logic_sv2::utils::hash_rate_to_target(
+            hash_rate.into(),
+            self.shares_per_minute.into(),
+        )?;
         self.channel_factory
             .safe_lock(|s| s.update_target_for_channel(m.channel_id, maximum_target.clone().into()))
             .unwrap_or_else(|_| {
@@ -110,12 +140,14 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
         match res {
             Ok(res) => match res  {
                 roles_logic_sv2::channel_logic::channel_factory::OnNewShare::SendErrorDownstream(m) => {
+                    self.record_share_error(&m);
                     Ok(SendTo::Respond(Mining::SubmitSharesError(m)))
                 }
                 roles_logic_sv2::channel_logic::channel_factory::OnNewShare::SendSubmitShareUpstream(_) => unreachable!(),
                 roles_logic_sv2::channel_logic::channel_factory::OnNewShare::RelaySubmitShareUpstream => unreachable!(),
                 roles_logic_sv2::channel_logic::channel_factory::OnNewShare::ShareMeetBitcoinTarget((share,t_id,coinbase,_)) => {
                     if let Some(template_id) = t_id {
+                        self.record_found_block(m.channel_id, template_id, &coinbase)?;
                         let solution = SubmitSolution {
                             template_id,
                             version: share.get_version(),
@@ -123,16 +155,16 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
                             header_nonce: share.get_nonce(),
                             coinbase_tx: coinbase.try_into()?,
                         };
-                        // TODO we can block everything with the below (looks like this will infinite loop??)
-                        while self.solution_sender.try_send(solution.clone()).is_err() {};
+                        self.submit_solution(solution);
                     }
+                    let hash = self.last_share_hash(m.channel_id)?;
+                    self.log_share(m.channel_id, m.sequence_number, hash, ShareOutcome::Unissued);
                     let success = SubmitSharesSuccess {
                         channel_id: m.channel_id,
                         last_sequence_number: m.sequence_number,
                         new_submits_accepted_count: 1,
                         new_shares_sum: 0,
-                        // initialize to all zeros, will be updated later
-                        hash: [0u8; 32].into(),
+                        hash: hash.into(),
                         blind_signatures: Sv2BlindSignatureSetWire::default(),
                     };
 
@@ -140,13 +172,14 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
 
                 },
                 roles_logic_sv2::channel_logic::channel_factory::OnNewShare::ShareMeetDownstreamTarget => {
-                 let success = SubmitSharesSuccess {
+                    let hash = self.last_share_hash(m.channel_id)?;
+                    self.log_share(m.channel_id, m.sequence_number, hash, ShareOutcome::Unissued);
+                    let success = SubmitSharesSuccess {
                         channel_id: m.channel_id,
                         last_sequence_number: m.sequence_number,
                         new_submits_accepted_count: 1,
                         new_shares_sum: 0,
-                        // initialize to all zeros, will be updated later
-                        hash: [0u8; 32].into(),
+                        hash: hash.into(),
                         blind_signatures: Sv2BlindSignatureSetWire::default(),
                     };
                     Ok(SendTo::Respond(Mining::SubmitSharesSuccess(success)))
@@ -160,6 +193,19 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
         &mut self,
         m: SubmitSharesExtended,
     ) -> Result<SendTo<()>, Error> {
+        // every log of the share, down to its issuance, carries its identity and once known its
+        // hash, which the translator logs when it mints the ehash
+        let span = info_span!(
+            "share",
+            downstream = self.id,
+            channel = m.channel_id,
+            sequence = m.sequence_number,
+            hash = field::Empty,
+        );
+        let _enter = span.enter();
+        if self.is_resubmitted_share(&m) {
+            return self.reject_duplicate_share(&m);
+        }
         let res = self
             .channel_factory
             .safe_lock(|cf| cf.on_submit_shares_extended(m.clone()))
@@ -167,12 +213,19 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
         match res {
             Ok(res) => match res  {
                 roles_logic_sv2::channel_logic::channel_factory::OnNewShare::SendErrorDownstream(m) => {
+                    self.record_share_error(&m);
                     Ok(SendTo::Respond(Mining::SubmitSharesError(m)))
                 }
                 roles_logic_sv2::channel_logic::channel_factory::OnNewShare::SendSubmitShareUpstream(_) => unreachable!(),
                 roles_logic_sv2::channel_logic::channel_factory::OnNewShare::RelaySubmitShareUpstream => unreachable!(),
                 roles_logic_sv2::channel_logic::channel_factory::OnNewShare::ShareMeetBitcoinTarget((share,t_id,coinbase,_)) => {
+                    let hash = self.last_share_hash(m.channel_id)?;
+                    if !self.is_new_share_hash(hash) {
+                        return self.reject_duplicate_share(&m);
+                    }
+                    self.remember_share(&m);
                     if let Some(template_id) = t_id {
+                        self.record_found_block(m.channel_id, template_id, &coinbase)?;
                         let solution = SubmitSolution {
                             template_id,
                             version: share.get_version(),
@@ -180,38 +233,20 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
                             header_nonce: share.get_nonce(),
                             coinbase_tx: coinbase.try_into()?,
                         };
-                        // TODO we can block everything with the below (looks like this will infinite loop??)
-                        while self.solution_sender.try_send(solution.clone()).is_err() {};
+                        self.submit_solution(solution);
                     }
 
-                    let blind_signatures = self.sign_blinded_messages(m.blinded_messages.clone()).into_static();
-
-                    let success = SubmitSharesSuccess {
-                        channel_id: m.channel_id,
-                        last_sequence_number: m.sequence_number,
-                        new_submits_accepted_count: 1,
-                        new_shares_sum: 0,
-                        blind_signatures,
-                        // TODO is this ownership hack fixable?
-                        hash: m.hash.inner_as_ref().to_owned().try_into()?,
-                    };
-
-                    Ok(SendTo::Respond(Mining::SubmitSharesSuccess(success)))
-
+                    let response = self.accepted_share_response(&m, hash)?;
+                    self.retarget(m.channel_id, response)
                 },
                 roles_logic_sv2::channel_logic::channel_factory::OnNewShare::ShareMeetDownstreamTarget => {
-                    let blind_signatures = self.sign_blinded_messages(m.blinded_messages.clone()).into_static();
-
-                    let success = SubmitSharesSuccess {
-                        channel_id: m.channel_id,
-                        last_sequence_number: m.sequence_number,
-                        new_submits_accepted_count: 1,
-                        new_shares_sum: 0,
-                        blind_signatures,
-                        // TODO is this ownership hack fixable?
-                        hash: m.hash.inner_as_ref().to_owned().try_into()?,
-                    };
-                    Ok(SendTo::Respond(Mining::SubmitSharesSuccess(success)))
+                    let hash = self.last_share_hash(m.channel_id)?;
+                    if !self.is_new_share_hash(hash) {
+                        return self.reject_duplicate_share(&m);
+                    }
+                    self.remember_share(&m);
+                    let response = self.accepted_share_response(&m, hash)?;
+                    self.retarget(m.channel_id, response)
                 },
             },
             Err(e) => {
@@ -222,6 +257,22 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
     }
 
     fn handle_set_custom_mining_job(&mut self, m: SetCustomMiningJob) -> Result<SendTo<()>, Error> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        if let Err(rejection) = self.custom_job_policy.check(&m, now) {
+            warn!(
+                "Rejecting custom job {} of downstream {}: {}",
+                m.request_id, self.id, rejection
+            );
+            let error = SetCustomMiningJobError {
+                channel_id: m.channel_id,
+                request_id: m.request_id,
+                error_code: rejection.error_code().try_into()?,
+            };
+            return Ok(SendTo::Respond(Mining::SetCustomMiningJobError(error)));
+        }
         let m = SetCustomMiningJobSuccess {
             channel_id: m.channel_id,
             request_id: m.request_id,
@@ -234,16 +285,402 @@ impl ParseDownstreamMiningMessages<(), NullDownstreamMiningSelector, NoRouting>
     }
 }
 
+/// Number of recent shares remembered per channel to detect resubmissions
+const RECENT_SHARES_PER_CHANNEL: usize = 1024;
+
+/// How long a block solution waits for room in the channel to the template receiver.
+const SOLUTION_SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
 impl Downstream {
+    /// Hands a block solution to the template receiver without blocking share handling. When
+    /// the channel is full the send is retried in the background until SOLUTION_SEND_TIMEOUT.
+    fn submit_solution(&self, solution: SubmitSolution<'static>) {
+        let solution = match self.solution_sender.try_send(solution) {
+            Ok(()) => return,
+            Err(async_channel::TrySendError::Full(solution)) => solution,
+            Err(async_channel::TrySendError::Closed(_)) => {
+                error!(
+                    "Template receiver is gone, block solution found by downstream {} is lost",
+                    self.id
+                );
+                return;
+            }
+        };
+        warn!("Solution channel full, waiting for the template receiver");
+        let sender = self.solution_sender.clone();
+        let id = self.id;
+        tokio::task::spawn(async move {
+            match tokio::time::timeout(SOLUTION_SEND_TIMEOUT, sender.send(solution)).await {
+                Ok(Ok(())) => (),
+                Ok(Err(_)) => error!(
+                    "Template receiver is gone, block solution found by downstream {} is lost",
+                    id
+                ),
+                Err(_) => error!(
+                    "Template receiver stuck for {:?}, block solution found by downstream {} is lost",
+                    SOLUTION_SEND_TIMEOUT, id
+                ),
+            }
+        });
+    }
+
+    /// Scores a rejected share, shares for a job that is gone count as stale.
+    fn record_share_error(&mut self, error: &SubmitSharesError) {
+        let code = error.error_code.to_vec();
+        let offense = if code == SubmitSharesError::stale_share_error_code().as_bytes()
+            || code == SubmitSharesError::invalid_job_id_error_code().as_bytes()
+        {
+            Offense::StaleShare
+        } else {
+            Offense::InvalidShare
+        };
+        self.misbehavior.record(offense, Instant::now());
+    }
+
+    /// Whether the share was already accepted on its channel.
+    fn is_resubmitted_share(&self, m: &SubmitSharesExtended) -> bool {
+        self.recent_shares
+            .get(&m.channel_id)
+            .map_or(false, |shares| shares.contains(&share_key(m)))
+    }
+
+    /// Remembers a share the channel factory accepted, so that it is rejected if submitted
+    /// again. Shares are only remembered once valid, a rejected share can be fixed and resent.
+    fn remember_share(&mut self, m: &SubmitSharesExtended) {
+        self.recent_shares
+            .entry(m.channel_id)
+            .or_insert_with(|| RecentShares::new(RECENT_SHARES_PER_CHANNEL))
+            .insert(share_key(m));
+    }
+
+    /// Records the hash of an accepted share in the pool wide cache, returning `false` if a share
+    /// with the same hash was already accepted, on any channel of any downstream.
+    fn is_new_share_hash(&self, hash: [u8; 32]) -> bool {
+        match self.recent_share_hashes.safe_lock(|s| s.insert(hash)) {
+            Ok(new) => new,
+            Err(e) => {
+                error!("Failed to lock recent share hashes: {}", e);
+                true
+            }
+        }
+    }
+
+    fn reject_duplicate_share(&mut self, m: &SubmitSharesExtended) -> Result<SendTo<()>, Error> {
+        warn!(
+            "Downstream {} resubmitted share {} on channel {}",
+            self.id, m.sequence_number, m.channel_id
+        );
+        self.misbehavior.record(Offense::DuplicateShare, Instant::now());
+        let error = SubmitSharesError {
+            channel_id: m.channel_id,
+            sequence_number: m.sequence_number,
+            error_code: SubmitSharesError::duplicate_share_error_code()
+                .to_string()
+                .try_into()?,
+        };
+        Ok(SendTo::Respond(Mining::SubmitSharesError(error)))
+    }
+
+    /// Starts the vardiff loop of a new channel from the hashrate it advertised.
+    fn track_channel(&mut self, channel_id: u32, hashrate: f32) {
+        let vardiff = ChannelVardiff::new(
+            self.vardiff_config.clone(),
+            self.shares_per_minute,
+            hashrate,
+            Instant::now(),
+        );
+        self.vardiff.insert(channel_id, vardiff);
+        self.hashrate.insert(
+            channel_id,
+            HashrateEstimator::new(self.hashrate_window, Instant::now()),
+        );
+    }
+
+    /// Feeds an accepted share to the vardiff loop of its channel, adding a `SetTarget` to the
+    /// response when the channel target has to change.
+    fn retarget(&mut self, channel_id: u32, response: SendTo<()>) -> Result<SendTo<()>, Error> {
+        // the channel target is set for shares_per_minute at the vardiff hashrate, so each share
+        // is expected to take that many hashes
+        if let (Some(vardiff), Some(estimator)) = (
+            self.vardiff.get(&channel_id),
+            self.hashrate.get_mut(&channel_id),
+        ) {
+            let work = vardiff.hashrate() as f64 * 60.0 / self.shares_per_minute as f64;
+            estimator.on_accepted_share(work, Instant::now());
+        }
+        let hashrate = match self
+            .vardiff
+            .get_mut(&channel_id)
+            .and_then(|vardiff| vardiff.on_accepted_share(Instant::now()))
+        {
+            Some(hashrate) => hashrate.max(self.fixed_minimum_hashrate),
+            None => return Ok(response),
+        };
+        let maximum_target = roles_logic_sv2::utils::hash_rate_to_target(
+            hashrate.into(),
+            self.shares_per_minute.into(),
+        )?;
+        self.channel_factory
+            .safe_lock(|s| s.update_target_for_channel(channel_id, maximum_target.clone().into()))
+            .map_err(|e| Error::PoisonLock(e.to_string()))?;
+        info!(
+            "Vardiff: channel {} of downstream {} retargeted to {:.0} h/s",
+            channel_id, self.id, hashrate
+        );
+        let set_target = SetTarget {
+            channel_id,
+            maximum_target,
+        };
+        Ok(SendTo::Multiple(vec![
+            response,
+            SendTo::Respond(Mining::SetTarget(set_target)),
+        ]))
+    }
+
+    /// Logs and persists a share that meets the bitcoin target.
+    fn record_found_block(
+        &self,
+        channel_id: u32,
+        template_id: u64,
+        coinbase: &[u8],
+    ) -> Result<(), Error> {
+        let hash = self.last_share_hash(channel_id)?;
+        let block = FoundBlock::new(hash, coinbase, template_id, self.id, channel_id);
+        let (result, count) = self
+            .found_blocks
+            .safe_lock(|f| (f.record(&block), f.count()))
+            .map_err(|e| Error::PoisonLock(e.to_string()))?;
+        info!(
+            "BLOCK FOUND: hash {}, height {:?}, template {}, downstream {}, channel {} ({} since start)",
+            hash.to_lower_hex_string(),
+            block.height,
+            template_id,
+            self.id,
+            channel_id,
+            count
+        );
+        if let Err(e) = result {
+            error!("Failed to persist found block: {}", e);
+        }
+        Ok(())
+    }
+
+    /// Hash of the share just checked by the channel factory on `channel_id`, as computed by the
+    /// pool rather than as claimed by the downstream.
+    fn last_share_hash(&self, channel_id: u32) -> Result<[u8; 32], Error> {
+        self.channel_factory
+            .safe_lock(|cf| cf.last_share_hash(channel_id))
+            .map_err(|e| Error::PoisonLock(e.to_string()))?
+            .ok_or(Error::ShareDoNotMatchAnyChannel)
+    }
+
+    /// Answers an accepted share with its blind signatures, or with a `SubmitSharesError` carrying
+    /// the reason when no ehash is issued for it. The share still counts for vardiff either way.
+    fn accepted_share_response(
+        &mut self,
+        m: &SubmitSharesExtended,
+        share_hash: [u8; 32],
+    ) -> Result<SendTo<()>, Error> {
+        match self.issue_blind_signatures(m, share_hash) {
+            Ok(blind_signatures) => {
+                let success = SubmitSharesSuccess {
+                    channel_id: m.channel_id,
+                    last_sequence_number: m.sequence_number,
+                    new_submits_accepted_count: 1,
+                    new_shares_sum: 0,
+                    blind_signatures,
+                    hash: share_hash.into(),
+                };
+                Ok(SendTo::Respond(Mining::SubmitSharesSuccess(success)))
+            }
+            Err(error_code) => {
+                let error = SubmitSharesError {
+                    channel_id: m.channel_id,
+                    sequence_number: m.sequence_number,
+                    error_code: error_code.to_string().try_into()?,
+                };
+                Ok(SendTo::Respond(Mining::SubmitSharesError(error)))
+            }
+        }
+    }
+
+    /// Signs the blinded messages of an accepted share unless the issuance circuit breaker is
+    /// tripped for this downstream, in which case they are parked in the review queue. Returns
+    /// the error code to send back when no signatures are issued.
+    fn issue_blind_signatures(
+        &mut self,
+        m: &SubmitSharesExtended,
+        share_hash: [u8; 32],
+    ) -> Result<Sv2BlindSignatureSetWire<'static>, &'static str> {
+        Span::current().record("hash", field::display(share_hash.to_lower_hex_string()));
+        let (blind_signatures, outcome) = self.sign_or_divert(m, share_hash);
+        debug!("Accepted share, ehash {}", outcome);
+        self.log_share(m.channel_id, m.sequence_number, share_hash, outcome);
+        blind_signatures
+    }
+
+    fn sign_or_divert(
+        &mut self,
+        m: &SubmitSharesExtended,
+        share_hash: [u8; 32],
+    ) -> (
+        Result<Sv2BlindSignatureSetWire<'static>, &'static str>,
+        ShareOutcome,
+    ) {
+        match self.share_rate_monitor.on_accepted_share(Instant::now()) {
+            Issuance::Allowed => {
+                let (blind_signatures, outcome) = self.sign_blinded_messages(
+                    m.channel_id,
+                    m.blinded_messages.clone(),
+                    &share_hash,
+                );
+                return (blind_signatures.map(|s| s.into_static()), outcome);
+            }
+            Issuance::Tripped => error!(
+                "Issuance circuit breaker tripped for downstream {}: {} shares in the current window, trailing average {:.2}. Diverting ehash issuance to the review queue",
+                self.id,
+                self.share_rate_monitor.current_window_shares(),
+                self.share_rate_monitor.trailing_average()
+            ),
+            Issuance::Diverted => (),
+        }
+
+        let diverted = DivertedIssuance {
+            downstream_id: self.id,
+            channel_id: m.channel_id,
+            sequence_number: m.sequence_number,
+            hash: share_hash,
+            blinded_messages: m.blinded_messages.clone().into_static(),
+        };
+        let pushed = self.review_queue.safe_lock(|q| {
+            let evicted = q.push(diverted);
+            (evicted, q.len())
+        });
+        match pushed {
+            Ok((Some(evicted), _)) => error!(
+                "Review queue full, dropped diverted issuance of downstream {} sequence {}",
+                evicted.downstream_id, evicted.sequence_number
+            ),
+            Ok((None, len)) => warn!(
+                "Diverted issuance of downstream {} sequence {}, {} pending review",
+                self.id, m.sequence_number, len
+            ),
+            Err(e) => error!("Failed to lock review queue: {}", e),
+        }
+        (
+            Err(SubmitSharesError::ehash_diverted_error_code()),
+            ShareOutcome::Diverted,
+        )
+    }
+
+    /// Signs the blinded messages of an issuance the operator approved after the circuit breaker
+    /// diverted it, returning the `SubmitSharesSuccess` that delivers the signatures for the
+    /// share. The share was already counted when it was accepted, it is only logged again with
+    /// its new outcome.
+    pub fn sign_diverted(
+        &self,
+        issuance: &DivertedIssuance,
+    ) -> Result<SubmitSharesSuccess<'static>, &'static str> {
+        let (blind_signatures, outcome) = self.sign_blinded_messages(
+            issuance.channel_id,
+            issuance.blinded_messages.clone(),
+            &issuance.hash,
+        );
+        info!("Approved diverted issuance: {}, ehash {}", issuance, outcome);
+        self.log_share(
+            issuance.channel_id,
+            issuance.sequence_number,
+            issuance.hash,
+            outcome,
+        );
+        Ok(SubmitSharesSuccess {
+            channel_id: issuance.channel_id,
+            last_sequence_number: issuance.sequence_number,
+            new_submits_accepted_count: 0,
+            new_shares_sum: 0,
+            blind_signatures: blind_signatures?.into_static(),
+            hash: issuance.hash.into(),
+        })
+    }
+
+    /// Appends an accepted share to the share log, if enabled.
+    fn log_share(
+        &self,
+        channel_id: u32,
+        sequence_number: u32,
+        hash: [u8; 32],
+        outcome: ShareOutcome,
+    ) {
+        if let Some(share_log) = &self.share_log {
+            let record = ShareRecord::now(
+                self.id,
+                channel_id,
+                sequence_number,
+                hash,
+                share_work(&hash),
+                outcome,
+            );
+            match share_log.safe_lock(|log| log.append(&record)) {
+                Ok(Ok(())) => (),
+                Ok(Err(e)) => error!("Failed to append to share log: {}", e),
+                Err(e) => error!("Failed to lock share log: {}", e),
+            }
+        }
+    }
+
     fn sign_blinded_messages(
         &self,
+        channel_id: u32,
         blinded_messages: Sv2BlindedMessageSetWire,
-    ) -> Sv2BlindSignatureSetWire {
+        share_hash: &[u8; 32],
+    ) -> (Result<Sv2BlindSignatureSetWire, &'static str>, ShareOutcome) {
         let mint_clone = Arc::clone(&self.mint);
 
         // convert to cdk structs
-        let blinded_message_set = BlindedMessageSet::try_from(blinded_messages.clone())
-            .expect("Failed to convert Sv2BlindedMessageSetWire to BlindedMessageSet");
+        let blinded_message_set = match BlindedMessageSet::try_from(blinded_messages.clone()) {
+            Ok(blinded_message_set) => blinded_message_set,
+            Err(e) => {
+                error!("Downstream {} sent invalid blinded messages: {:?}", self.id, e);
+                return (
+                    Err(SubmitSharesError::invalid_blinded_messages_error_code()),
+                    ShareOutcome::Refused,
+                );
+            }
+        };
+
+        let amount = blinded_message_set
+            .items
+            .iter()
+            .flatten()
+            .map(|m| u64::from(m.amount))
+            .fold(0u64, |total, amount| total.saturating_add(amount));
+        if amount > self.mint_config.max_amount {
+            error!(
+                "Downstream {} requested {} ehash for a single share, above the max amount of {}",
+                self.id, amount, self.mint_config.max_amount
+            );
+            return (
+                Err(SubmitSharesError::ehash_amount_too_large_error_code()),
+                ShareOutcome::Refused,
+            );
+        }
+        let work = share_work(share_hash);
+        if amount > work {
+            if self.mint_config.enforce_share_work {
+                error!(
+                    "Downstream {} requested {} ehash for a share worth {}, rejecting issuance",
+                    self.id, amount, work
+                );
+                return (
+                    Err(SubmitSharesError::ehash_above_share_work_error_code()),
+                    ShareOutcome::Refused,
+                );
+            }
+            warn!(
+                "Downstream {} requested {} ehash for a share worth {}",
+                self.id, amount, work
+            );
+        }
 
         // sign messages
         let blinded_signature_set = tokio::task::block_in_place(move || {
@@ -254,8 +691,23 @@ impl Downstream {
             result.expect("Failed to lock mint")
         });
 
+        if let Some(issuance_log) = &self.issuance_log {
+            let record = IssuanceRecord::now(
+                self.id,
+                channel_id,
+                *share_hash,
+                blinded_signature_set.keyset_id,
+                amount,
+            );
+            match issuance_log.safe_lock(|log| log.append(&record)) {
+                Ok(Ok(())) => (),
+                Ok(Err(e)) => error!("Failed to append to issuance log: {}", e),
+                Err(e) => error!("Failed to lock issuance log: {}", e),
+            }
+        }
+
         // convert back to wire format
-        blinded_signature_set.into()
+        (Ok(blinded_signature_set.into()), ShareOutcome::Issued(amount))
     }
 
     fn sign_message_set(
@@ -269,6 +721,10 @@ impl Downstream {
                 let signature = tokio::runtime::Handle::current()
                     .block_on(mint.blind_sign(blinded_message))
                     .expect("Failed to get blind signature");
+                // the wallet rejects signatures it cannot check against the published keyset
+                if signature.dleq.is_none() {
+                    warn!("Mint returned a blind signature without a DLEQ proof");
+                }
                 items[i] = Some(signature);
             }
         }
@@ -280,4 +736,44 @@ impl Downstream {
     }
 }
 
-//TODO unit test sign_message_set and sign_blinded_messages
\ No newline at end of file
+fn share_key(m: &SubmitSharesExtended) -> ShareKey {
+    ShareKey {
+        job_id: m.job_id,
+        nonce: m.nonce,
+        ntime: m.ntime,
+        version: m.version,
+        extranonce: m.extranonce.to_vec(),
+    }
+}
+
+/// Work proven by a share, counted as the leading zero bits of its big-endian hash. This is the
+/// amount the translator requests for a share.
+fn share_work(hash: &[u8; 32]) -> u64 {
+    let mut work = 0u64;
+    for byte in hash {
+        if *byte == 0 {
+            work += 8;
+        } else {
+            work += byte.leading_zeros() as u64;
+            break;
+        }
+    }
+    work
+}
+
+//TODO unit test sign_message_set and sign_blinded_messages
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn share_work_counts_leading_zero_bits() {
+        assert_eq!(share_work(&[0xff; 32]), 0);
+        let mut hash = [0xff; 32];
+        hash[0] = 0;
+        hash[1] = 0x1f;
+        assert_eq!(share_work(&hash), 11);
+        assert_eq!(share_work(&[0; 32]), 256);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,149 @@
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+/// Settings for downstream misbehavior scoring. Every offense adds its weight to the score of
+/// the connection, the score decays by `decay_per_minute` and once it reaches `ban_threshold`
+/// the downstream is disconnected and its address refused for `ban_duration_secs`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct MisbehaviorConfig {
+    pub enabled: bool,
+    pub ban_threshold: f32,
+    pub decay_per_minute: f32,
+    pub ban_duration_secs: u64,
+    /// Weight of a share rejected for anything but staleness or being a duplicate
+    pub invalid_share: f32,
+    /// Weight of a share for a job that is no longer valid
+    pub stale_share: f32,
+    /// Weight of a share that was already accepted. A translator replays its unacknowledged
+    /// shares after a reconnect, so duplicates are not scored by default
+    pub duplicate_share: f32,
+    /// Weight of a frame that cannot be decoded
+    pub malformed_frame: f32,
+}
+
+impl Default for MisbehaviorConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ban_threshold: 100.0,
+            decay_per_minute: 10.0,
+            ban_duration_secs: 600,
+            invalid_share: 10.0,
+            stale_share: 1.0,
+            duplicate_share: 0.0,
+            malformed_frame: 50.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Offense {
+    InvalidShare,
+    StaleShare,
+    DuplicateShare,
+    MalformedFrame,
+}
+
+/// Misbehavior score of a single downstream connection.
+#[derive(Debug)]
+pub struct MisbehaviorScore {
+    config: MisbehaviorConfig,
+    score: f32,
+    updated_at: Instant,
+}
+
+impl MisbehaviorScore {
+    pub fn new(config: MisbehaviorConfig, now: Instant) -> Self {
+        Self {
+            config,
+            score: 0.0,
+            updated_at: now,
+        }
+    }
+
+    pub fn record(&mut self, offense: Offense, now: Instant) {
+        if !self.config.enabled {
+            return;
+        }
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f32();
+        let decayed = self.score - elapsed / 60.0 * self.config.decay_per_minute;
+        let weight = match offense {
+            Offense::InvalidShare => self.config.invalid_share,
+            Offense::StaleShare => self.config.stale_share,
+            Offense::DuplicateShare => self.config.duplicate_share,
+            Offense::MalformedFrame => self.config.malformed_frame,
+        };
+        self.score = decayed.max(0.0) + weight;
+        self.updated_at = now;
+    }
+
+    /// Whether the downstream has to be disconnected and banned.
+    pub fn exceeded(&self) -> bool {
+        self.config.enabled && self.score >= self.config.ban_threshold
+    }
+}
+
+/// Addresses temporarily refused by the pool.
+#[derive(Debug)]
+pub struct BanList {
+    duration: Duration,
+    banned: HashMap<IpAddr, Instant>,
+}
+
+impl BanList {
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            duration,
+            banned: HashMap::new(),
+        }
+    }
+
+    pub fn ban(&mut self, ip: IpAddr, now: Instant) {
+        self.banned.insert(ip, now + self.duration);
+    }
+
+    /// Whether `ip` is banned at `now`, forgetting about the bans that expired.
+    pub fn is_banned(&mut self, ip: IpAddr, now: Instant) -> bool {
+        self.banned.retain(|_, until| *until > now);
+        self.banned.contains_key(&ip)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn score_decays_and_bans_expire() {
+        let start = Instant::now();
+        let config = MisbehaviorConfig {
+            enabled: true,
+            ..Default::default()
+        };
+        let mut score = MisbehaviorScore::new(config, start);
+
+        // 9 invalid shares stay below the threshold of 100
+        for _ in 0..9 {
+            score.record(Offense::InvalidShare, start);
+        }
+        assert!(!score.exceeded());
+
+        // a minute later 10 points decayed, so the 10th invalid share is not enough
+        let later = start + Duration::from_secs(60);
+        score.record(Offense::InvalidShare, later);
+        assert!(!score.exceeded());
+        score.record(Offense::MalformedFrame, later);
+        assert!(score.exceeded());
+
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let mut bans = BanList::new(Duration::from_secs(600));
+        bans.ban(ip, start);
+        assert!(bans.is_banned(ip, start + Duration::from_secs(599)));
+        assert!(!bans.is_banned(ip, start + Duration::from_secs(600)));
+    }
+}
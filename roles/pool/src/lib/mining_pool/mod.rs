@@ -26,8 +26,9 @@ use serde::Deserialize;
 use std::{
     collections::HashMap,
     convert::{TryFrom, TryInto},
-    net::SocketAddr,
+    net::{IpAddr, SocketAddr},
     sync::Arc,
+    time::{Duration, Instant},
 };
 use stratum_common::{
     bitcoin::{Script, TxOut},
@@ -37,10 +38,63 @@ use tokio::{net::TcpListener, task};
 use tracing::{debug, error, info, warn};
 
 pub mod setup_connection;
-use setup_connection::SetupConnectionHandler;
+use setup_connection::{ClientInfo, SetupConnectionHandler};
 
 pub mod message_handler;
-use mining_sv2::cashu::Sv2KeySet;
+use mining_sv2::cashu::{Sv2KeySet, NUM_MESSAGES};
+
+pub mod circuit_breaker;
+use circuit_breaker::{take_review_decisions, CircuitBreakerConfig, ReviewQueue, ShareRateMonitor};
+
+pub mod issuance_log;
+use issuance_log::IssuanceLog;
+
+pub mod vardiff;
+use vardiff::{ChannelVardiff, VardiffConfig};
+
+pub mod share_cache;
+use share_cache::{RecentShares, RECENT_SHARE_HASHES};
+
+pub mod found_blocks;
+use found_blocks::FoundBlocks;
+
+pub mod share_log;
+use share_log::{ShareLog, ShareLogConfig};
+
+pub mod misbehavior;
+use misbehavior::{BanList, MisbehaviorConfig, MisbehaviorScore, Offense};
+
+pub mod job_policy;
+use job_policy::{CustomJobPolicy, CustomJobPolicyConfig};
+
+pub mod hashrate;
+use hashrate::{format_hashrate, HashrateEstimator};
+
+pub mod rate_limit;
+use rate_limit::{RateLimitConfig, RateLimiter};
+
+pub mod connection_limit;
+use connection_limit::{ConnectionLimitConfig, ConnectionLimiter, ConnectionSlot};
+
+fn default_shares_per_minute() -> f32 {
+    1.0
+}
+
+fn default_tp_failback_interval_secs() -> u64 {
+    30
+}
+
+fn default_tp_reconnect_max_backoff_secs() -> u64 {
+    60
+}
+
+fn default_drain_period_secs() -> u64 {
+    60
+}
+
+fn default_hashrate_window_secs() -> u64 {
+    600
+}
 
 pub type Message = PoolMessages<'static>;
 pub type StdFrame = StandardSv2Frame<Message>;
@@ -62,10 +116,64 @@ pub fn get_coinbase_output(config: &Configuration) -> Result<Vec<TxOut>, Error>
     }
 }
 
+/// Percentage of the coinbase value paid to each configured output, `None` when no output sets a
+/// percentage and the whole value goes to the first output. Either every output sets one or none
+/// does, and the percentages must sum up to 100.
+pub fn get_coinbase_split(config: &Configuration) -> Result<Option<Vec<f64>>, String> {
+    let outputs = &config.coinbase_outputs;
+    if outputs.iter().all(|o| o.percentage.is_none()) {
+        return Ok(None);
+    }
+    let mut split = Vec::with_capacity(outputs.len());
+    for (i, output) in outputs.iter().enumerate() {
+        match output.percentage {
+            Some(p) if p > 0.0 && p <= 100.0 => split.push(p),
+            Some(p) => {
+                return Err(format!(
+                    "coinbase_outputs[{}].percentage must be above 0 and at most 100, got {}",
+                    i, p
+                ))
+            }
+            None => {
+                return Err(format!(
+                    "coinbase_outputs[{}].percentage is missing, set it on every output or on none",
+                    i
+                ))
+            }
+        }
+    }
+    let total: f64 = split.iter().sum();
+    if (total - 100.0).abs() > 1e-6 {
+        return Err(format!(
+            "coinbase_outputs percentages must sum up to 100, got {}",
+            total
+        ));
+    }
+    Ok(Some(split))
+}
+
+/// Sets the value of the pool outputs for a template leaving `value` to the pool. Every output
+/// but the first is paid its percentage rounded down, the first output takes what is left so
+/// rounding never burns any value.
+fn split_coinbase_value(outputs: &mut [TxOut], split: &[f64], value: u64) {
+    let mut rest = value;
+    for (output, percentage) in outputs.iter_mut().zip(split).skip(1) {
+        let share = (value as f64 * percentage / 100.0).floor() as u64;
+        output.value = share.min(rest);
+        rest -= output.value;
+    }
+    if let Some(first) = outputs.first_mut() {
+        first.value = rest;
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct CoinbaseOutput {
     output_script_type: String,
     output_script_value: String,
+    /// Share of the coinbase value paid to this output, in percent
+    #[serde(default)]
+    percentage: Option<f64>,
 }
 
 impl CoinbaseOutput {
@@ -73,6 +181,7 @@ impl CoinbaseOutput {
         Self {
             output_script_type,
             output_script_value,
+            percentage: None,
         }
     }
 }
@@ -93,16 +202,150 @@ impl TryFrom<&CoinbaseOutput> for CoinbaseOutput_ {
     }
 }
 
+/// Settings of the mint embedded in the pool.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct MintConfig {
+    /// Number of power-of-two keys in the keyset (amounts 2^0 to 2^(num_keys - 1))
+    pub num_keys: u8,
+    /// Maximum ehash amount issued for a single share
+    pub max_amount: u64,
+    /// Reject shares whose blinded messages request more ehash than the work proven by the
+    /// share hash computed by the pool
+    pub enforce_share_work: bool,
+    /// Seconds a mint quote stays valid
+    pub mint_quote_ttl_secs: u64,
+    /// Seconds a melt quote stays valid
+    pub melt_quote_ttl_secs: u64,
+    /// File receiving a hash-chained record of every issuance, disabled if unset
+    pub issuance_log_path: Option<String>,
+}
+
+impl Default for MintConfig {
+    fn default() -> Self {
+        Self {
+            num_keys: 64,
+            max_amount: 256,
+            enforce_share_work: false,
+            mint_quote_ttl_secs: 1000,
+            melt_quote_ttl_secs: 1000,
+            issuance_log_path: None,
+        }
+    }
+}
+
+impl MintConfig {
+    /// Checks the settings against the capacity of the keyset wire format.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.num_keys == 0 || self.num_keys as usize > NUM_MESSAGES {
+            return Err(format!(
+                "mint.num_keys must be between 1 and {}, got {}",
+                NUM_MESSAGES, self.num_keys
+            ));
+        }
+        // the largest amount a set of distinct power-of-two denominations can sum up to
+        let max_representable = match 1u64.checked_shl(self.num_keys as u32) {
+            Some(v) => v - 1,
+            None => u64::MAX,
+        };
+        if self.max_amount == 0 || self.max_amount > max_representable {
+            return Err(format!(
+                "mint.max_amount must be between 1 and {} for {} keys, got {}",
+                max_representable, self.num_keys, self.max_amount
+            ));
+        }
+        if self.mint_quote_ttl_secs == 0 || self.melt_quote_ttl_secs == 0 {
+            return Err(format!(
+                "mint.mint_quote_ttl_secs and mint.melt_quote_ttl_secs must be non-zero, got {} and {}",
+                self.mint_quote_ttl_secs, self.melt_quote_ttl_secs
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Encrypted mining listener, with the policy applied to the connections it accepts.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ListenerConfig {
+    pub address: String,
+    /// Source addresses allowed to connect, any address if empty
+    #[serde(default)]
+    pub allowed_ips: Vec<IpAddr>,
+}
+
+impl ListenerConfig {
+    pub fn new(address: String) -> Self {
+        Self {
+            address,
+            allowed_ips: Vec::new(),
+        }
+    }
+
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        self.allowed_ips.is_empty() || self.allowed_ips.contains(&ip)
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Configuration {
     pub listen_address: String,
+    /// Listeners bound next to `listen_address`, e.g. a localhost one for a local JDC
+    #[serde(default)]
+    pub extra_listeners: Vec<ListenerConfig>,
     pub tp_address: String,
+    /// Template providers used, in order, while `tp_address` is unreachable
+    #[serde(default)]
+    pub tp_fallback_addresses: Vec<String>,
+    /// Seconds between two checks of whether `tp_address` is back while on a fallback
+    #[serde(default = "default_tp_failback_interval_secs")]
+    pub tp_failback_interval_secs: u64,
+    /// Longest wait in seconds between two attempts to reach a template provider once all of
+    /// them are down
+    #[serde(default = "default_tp_reconnect_max_backoff_secs")]
+    pub tp_reconnect_max_backoff_secs: u64,
+    /// Seconds the connected downstreams keep being served after a SIGTERM, before the pool
+    /// shuts down
+    #[serde(default = "default_drain_period_secs")]
+    pub drain_period_secs: u64,
+    /// Seconds of accepted shares the hashrate of each channel is estimated from
+    #[serde(default = "default_hashrate_window_secs")]
+    pub hashrate_window_secs: u64,
     pub tp_authority_public_key: Option<Secp256k1PublicKey>,
     pub authority_public_key: Secp256k1PublicKey,
     pub authority_secret_key: Secp256k1SecretKey,
     pub cert_validity_sec: u64,
     pub coinbase_outputs: Vec<CoinbaseOutput>,
     pub pool_signature: String,
+    /// Share rate the channel targets are derived for
+    #[serde(default = "default_shares_per_minute")]
+    pub shares_per_minute: f32,
+    /// Hashrate floor applied to the nominal hashrate of new and updated channels, so that
+    /// downstreams cannot request a lower difficulty than the pool is willing to serve
+    #[serde(default)]
+    pub fixed_minimum_hashrate: f32,
+    /// File every found block is appended to, disabled if unset
+    #[serde(default)]
+    pub found_blocks_path: Option<String>,
+    /// Seconds without any message from a downstream that has not opened a channel after which
+    /// it is disconnected, 0 disables
+    #[serde(default)]
+    pub idle_timeout_secs: u64,
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
+    #[serde(default)]
+    pub mint: MintConfig,
+    #[serde(default)]
+    pub vardiff: VardiffConfig,
+    #[serde(default)]
+    pub share_log: ShareLogConfig,
+    #[serde(default)]
+    pub misbehavior: MisbehaviorConfig,
+    #[serde(default)]
+    pub custom_job_policy: CustomJobPolicyConfig,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    #[serde(default)]
+    pub connection_limit: ConnectionLimitConfig,
     #[cfg(feature = "test_only_allow_unencrypted")]
     pub test_only_listen_adress_plain: String,
 }
@@ -161,13 +404,31 @@ impl Configuration {
     ) -> Self {
         Self {
             listen_address: pool_connection.listen_address,
+            extra_listeners: Vec::new(),
             tp_address: template_provider.address,
+            tp_fallback_addresses: Vec::new(),
+            tp_failback_interval_secs: default_tp_failback_interval_secs(),
+            tp_reconnect_max_backoff_secs: default_tp_reconnect_max_backoff_secs(),
+            drain_period_secs: default_drain_period_secs(),
+            hashrate_window_secs: default_hashrate_window_secs(),
             tp_authority_public_key: template_provider.authority_public_key,
             authority_public_key: authority_config.public_key,
             authority_secret_key: authority_config.secret_key,
             cert_validity_sec: pool_connection.cert_validity_sec,
             coinbase_outputs,
             pool_signature: pool_connection.signature,
+            shares_per_minute: default_shares_per_minute(),
+            fixed_minimum_hashrate: 0.0,
+            idle_timeout_secs: 0,
+            found_blocks_path: None,
+            circuit_breaker: CircuitBreakerConfig::default(),
+            mint: MintConfig::default(),
+            vardiff: VardiffConfig::default(),
+            share_log: ShareLogConfig::default(),
+            misbehavior: MisbehaviorConfig::default(),
+            custom_job_policy: CustomJobPolicyConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            connection_limit: ConnectionLimitConfig::default(),
             #[cfg(feature = "test_only_allow_unencrypted")]
             test_only_listen_adress_plain,
         }
@@ -183,6 +444,26 @@ pub struct Downstream {
     solution_sender: Sender<SubmitSolution<'static>>,
     channel_factory: Arc<Mutex<PoolChannelFactory>>,
     mint: Arc<Mutex<Mint>>,
+    share_rate_monitor: ShareRateMonitor,
+    review_queue: Arc<Mutex<ReviewQueue>>,
+    mint_config: MintConfig,
+    issuance_log: Option<Arc<Mutex<IssuanceLog>>>,
+    vardiff_config: VardiffConfig,
+    vardiff: HashMap<u32, ChannelVardiff, BuildNoHashHasher<u32>>,
+    shares_per_minute: f32,
+    fixed_minimum_hashrate: f32,
+    recent_shares: HashMap<u32, RecentShares, BuildNoHashHasher<u32>>,
+    /// Hashes of the shares recently accepted from any downstream
+    recent_share_hashes: Arc<Mutex<RecentShares<[u8; 32]>>>,
+    found_blocks: Arc<Mutex<FoundBlocks>>,
+    share_log: Option<Arc<Mutex<ShareLog>>>,
+    misbehavior: MisbehaviorScore,
+    custom_job_policy: CustomJobPolicy,
+    hashrate_window: Duration,
+    hashrate: HashMap<u32, HashrateEstimator, BuildNoHashHasher<u32>>,
+    client: ClientInfo,
+    /// Channels opened by the downstream, the idle timeout only applies while there are none
+    channel_ids: Vec<u32>,
 }
 
 // TODO remove after porting mint to use Sv2 data types
@@ -195,6 +476,8 @@ impl std::fmt::Debug for Downstream {
             .field("downstream_data", &self.downstream_data)
             .field("channel_factory", &self.channel_factory)
             .field("mint", &"debug not implemented")
+            .field("share_rate_monitor", &self.share_rate_monitor)
+            .field("vardiff", &self.vardiff)
             .finish()
     }
 }
@@ -208,6 +491,28 @@ pub struct Pool {
     last_prev_hash_template_id: u64,
     status_tx: status::Sender,
     mint: Arc<Mutex<Mint>>,
+    circuit_breaker: CircuitBreakerConfig,
+    review_queue: Arc<Mutex<ReviewQueue>>,
+    mint_config: MintConfig,
+    issuance_log: Option<Arc<Mutex<IssuanceLog>>>,
+    vardiff: VardiffConfig,
+    shares_per_minute: f32,
+    fixed_minimum_hashrate: f32,
+    found_blocks: Arc<Mutex<FoundBlocks>>,
+    share_log: Option<Arc<Mutex<ShareLog>>>,
+    coinbase_outputs: Vec<TxOut>,
+    /// Percentage of the coinbase value each of the pool outputs receives
+    coinbase_split: Option<Vec<f64>>,
+    misbehavior: MisbehaviorConfig,
+    bans: BanList,
+    custom_job_policy: CustomJobPolicyConfig,
+    /// Set once the pool is shutting down, new downstream connections are refused
+    draining: bool,
+    hashrate_window: Duration,
+    rate_limit: RateLimitConfig,
+    idle_timeout: Option<Duration>,
+    connection_limiter: Arc<Mutex<ConnectionLimiter>>,
+    recent_share_hashes: Arc<Mutex<RecentShares<[u8; 32]>>>,
 }
 
 impl Downstream {
@@ -220,9 +525,10 @@ impl Downstream {
         channel_factory: Arc<Mutex<PoolChannelFactory>>,
         status_tx: status::Sender,
         address: SocketAddr,
+        slot: ConnectionSlot,
     ) -> PoolResult<Arc<Mutex<Self>>> {
         let setup_connection = Arc::new(Mutex::new(SetupConnectionHandler::new()));
-        let downstream_data =
+        let (downstream_data, client) =
             SetupConnectionHandler::setup(setup_connection, &mut receiver, &mut sender, address)
                 .await?;
 
@@ -230,8 +536,45 @@ impl Downstream {
             false => channel_factory.safe_lock(|c| c.new_group_id())?,
             true => channel_factory.safe_lock(|c| c.new_standard_id_for_hom())?,
         };
+        info!("Downstream {} at {} connected, {}", id, address, client);
 
-        let mint = pool.safe_lock(|p| p.mint.clone())?;
+        let (
+            mint,
+            circuit_breaker,
+            review_queue,
+            mint_config,
+            issuance_log,
+            vardiff_config,
+            shares_per_minute,
+            fixed_minimum_hashrate,
+            found_blocks,
+            share_log,
+            misbehavior,
+            custom_job_policy,
+            hashrate_window,
+            rate_limit,
+            idle_timeout,
+            recent_share_hashes,
+        ) = pool.safe_lock(|p| {
+            (
+                p.mint.clone(),
+                p.circuit_breaker.clone(),
+                p.review_queue.clone(),
+                p.mint_config.clone(),
+                p.issuance_log.clone(),
+                p.vardiff.clone(),
+                p.shares_per_minute,
+                p.fixed_minimum_hashrate,
+                p.found_blocks.clone(),
+                p.share_log.clone(),
+                p.misbehavior.clone(),
+                CustomJobPolicy::new(p.custom_job_policy.clone(), p.coinbase_outputs.clone()),
+                p.hashrate_window,
+                p.rate_limit.clone(),
+                p.idle_timeout,
+                p.recent_share_hashes.clone(),
+            )
+        })?;
 
         let self_ = Arc::new(Mutex::new(Downstream {
             id,
@@ -241,6 +584,24 @@ impl Downstream {
             solution_sender,
             channel_factory,
             mint,
+            share_rate_monitor: ShareRateMonitor::new(circuit_breaker, std::time::Instant::now()),
+            review_queue,
+            mint_config,
+            issuance_log,
+            vardiff_config,
+            vardiff: HashMap::with_hasher(BuildNoHashHasher::default()),
+            shares_per_minute,
+            fixed_minimum_hashrate,
+            recent_shares: HashMap::with_hasher(BuildNoHashHasher::default()),
+            recent_share_hashes,
+            found_blocks,
+            share_log,
+            misbehavior: MisbehaviorScore::new(misbehavior, Instant::now()),
+            custom_job_policy,
+            hashrate_window,
+            hashrate: HashMap::with_hasher(BuildNoHashHasher::default()),
+            client,
+            channel_ids: Vec::new(),
         }));
 
         let cloned = self_.clone();
@@ -268,18 +629,57 @@ impl Downstream {
                     return;
                 }
             };
+            let mut rate_limiter = RateLimiter::new(rate_limit, Instant::now());
             loop {
-                match receiver.recv().await {
-                    Ok(received) => {
-                        let received: Result<StdFrame, _> = received
-                            .try_into()
-                            .map_err(|e| PoolError::Codec(codec_sv2::Error::FramingSv2Error(e)));
+                // a miner with an open channel can stay quiet for long on a high target, only
+                // connections that never opened a channel are timed out
+                let received = match idle_timeout {
+                    Some(idle_timeout) if !Downstream::has_open_channels(&cloned) => {
+                        tokio::time::timeout(idle_timeout, receiver.recv()).await
+                    }
+                    _ => Ok(receiver.recv().await),
+                };
+                match received {
+                    Ok(Ok(received)) => {
+                        if !rate_limiter.allow(Instant::now()) {
+                            warn!(
+                                "Downstream {} at {} exceeded the message rate limit, disconnecting it",
+                                id, address
+                            );
+                            let res = pool
+                                .safe_lock(|p| p.downstreams.remove(&id))
+                                .map_err(|e| PoolError::PoisonLock(e.to_string()));
+                            handle_result!(status_tx, res);
+                            break;
+                        }
+                        let received: Result<StdFrame, _> = received.try_into().map_err(|e| {
+                            Downstream::record_offense(&cloned, Offense::MalformedFrame);
+                            PoolError::Codec(codec_sv2::Error::FramingSv2Error(e))
+                        });
+                        // offenses are checked as frames come in, before a malformed frame
+                        // drops the connection
+                        if Downstream::ban_if_misbehaving(&cloned, &pool, address) {
+                            break;
+                        }
                         let std_frame = handle_result!(status_tx, received);
                         handle_result!(
                             status_tx,
                             Downstream::next(cloned.clone(), std_frame).await
                         );
                     }
+                    Err(_) => {
+                        warn!(
+                            "Downstream {} at {} opened no channel within {:?}, disconnecting it",
+                            id,
+                            address,
+                            idle_timeout.unwrap_or_default()
+                        );
+                        let res = pool
+                            .safe_lock(|p| p.downstreams.remove(&id))
+                            .map_err(|e| PoolError::PoisonLock(e.to_string()));
+                        handle_result!(status_tx, res);
+                        break;
+                    }
                     _ => {
                         let res = pool
                             .safe_lock(|p| p.downstreams.remove(&id))
@@ -291,10 +691,98 @@ impl Downstream {
                 }
             }
             warn!("Downstream connection dropped");
+            Downstream::remove_channels(&cloned);
+            // the connection no longer counts against the limits of its address
+            drop(slot);
         });
         Ok(self_)
     }
 
+    /// Software and device the downstream announced when it set up the connection.
+    pub fn client(&self) -> &ClientInfo {
+        &self.client
+    }
+
+    /// Estimated hashrate of each tracked channel, in hashes per second.
+    pub fn estimated_hashrates(&mut self) -> Vec<(u32, f64)> {
+        let now = Instant::now();
+        self.hashrate
+            .iter_mut()
+            .map(|(channel_id, estimator)| (*channel_id, estimator.estimate(now)))
+            .collect()
+    }
+
+    fn has_open_channels(self_: &Arc<Mutex<Self>>) -> bool {
+        match self_.safe_lock(|d| !d.channel_ids.is_empty()) {
+            Ok(open) => open,
+            Err(e) => {
+                error!("Failed to lock downstream: {}", e);
+                true
+            }
+        }
+    }
+
+    /// Removes the channels of a disconnected downstream from the channel factory.
+    fn remove_channels(self_: &Arc<Mutex<Self>>) {
+        let res = self_.safe_lock(|d| {
+            (
+                std::mem::take(&mut d.channel_ids),
+                d.channel_factory.clone(),
+            )
+        });
+        let (channel_ids, channel_factory) = match res {
+            Ok(res) => res,
+            Err(e) => {
+                error!("Failed to lock downstream: {}", e);
+                return;
+            }
+        };
+        let res = channel_factory.safe_lock(|cf| {
+            for channel_id in channel_ids {
+                cf.remove_channel(channel_id);
+            }
+        });
+        if let Err(e) = res {
+            error!("Failed to lock channel factory: {}", e);
+        }
+    }
+
+    fn record_offense(self_: &Arc<Mutex<Self>>, offense: Offense) {
+        if let Err(e) = self_.safe_lock(|d| d.misbehavior.record(offense, Instant::now())) {
+            error!("Failed to lock downstream: {}", e);
+        }
+    }
+
+    /// Drops the downstream from the pool and bans its address once its misbehavior score
+    /// reached the threshold. Returns whether the connection has to be closed.
+    fn ban_if_misbehaving(
+        self_: &Arc<Mutex<Self>>,
+        pool: &Arc<Mutex<Pool>>,
+        address: SocketAddr,
+    ) -> bool {
+        let (id, exceeded) = match self_.safe_lock(|d| (d.id, d.misbehavior.exceeded())) {
+            Ok(res) => res,
+            Err(e) => {
+                error!("Failed to lock downstream: {}", e);
+                return false;
+            }
+        };
+        if !exceeded {
+            return false;
+        }
+        warn!(
+            "Downstream {} at {} exceeded the misbehavior threshold, disconnecting and banning it",
+            id, address
+        );
+        if let Err(e) = pool.safe_lock(|p| {
+            p.downstreams.remove(&id);
+            p.bans.ban(address.ip(), Instant::now());
+        }) {
+            error!("Failed to lock pool: {}", e);
+        }
+        true
+    }
+
     pub async fn next(self_mutex: Arc<Mutex<Self>>, mut incoming: StdFrame) -> PoolResult<()> {
         let message_type = incoming
             .get_header()
@@ -412,6 +900,7 @@ impl Pool {
             .await
             .unwrap();
         let status_tx = self_.safe_lock(|s| s.status_tx.clone())?;
+        let connection_limiter = self_.safe_lock(|p| p.connection_limiter.clone())?;
 
         info!(
             "Listening for unencrypted connection on: {}",
@@ -419,6 +908,25 @@ impl Pool {
         );
         while let Ok((stream, _)) = listner.accept().await {
             let address = stream.peer_addr().unwrap();
+            let (draining, banned) = self_.safe_lock(|p| {
+                (p.draining, p.bans.is_banned(address.ip(), Instant::now()))
+            })?;
+            if draining {
+                info!("Draining, refusing connection from {}", address);
+                continue;
+            }
+            if banned {
+                warn!("Refusing connection from banned address {}", address);
+                continue;
+            }
+            let slot = ConnectionLimiter::acquire(&connection_limiter, address.ip(), Instant::now());
+            let slot = match slot {
+                Ok(slot) => slot,
+                Err(e) => {
+                    warn!("Refusing connection from {}: {:?}", address, e);
+                    continue;
+                }
+            };
             debug!("New connection from {}", address);
 
             let (receiver, sender): (Receiver<EitherFrame>, Sender<EitherFrame>) =
@@ -426,7 +934,8 @@ impl Pool {
 
             handle_result!(
                 status_tx,
-                Self::accept_incoming_connection_(self_.clone(), receiver, sender, address).await
+                Self::accept_incoming_connection_(self_.clone(), receiver, sender, address, slot)
+                    .await
             );
         }
         Ok(())
@@ -435,15 +944,43 @@ impl Pool {
     async fn accept_incoming_connection(
         self_: Arc<Mutex<Pool>>,
         config: Configuration,
+        listener_config: ListenerConfig,
     ) -> PoolResult<()> {
         let status_tx = self_.safe_lock(|s| s.status_tx.clone())?;
-        let listener = TcpListener::bind(&config.listen_address).await?;
+        let connection_limiter = self_.safe_lock(|p| p.connection_limiter.clone())?;
+        let listener = TcpListener::bind(&listener_config.address).await?;
         info!(
             "Listening for encrypted connection on: {}",
-            config.listen_address
+            listener_config.address
         );
         while let Ok((stream, _)) = listener.accept().await {
             let address = stream.peer_addr().unwrap();
+            if !listener_config.allows(address.ip()) {
+                warn!(
+                    "Refusing connection from {} on {}, address not allowed",
+                    address, listener_config.address
+                );
+                continue;
+            }
+            let (draining, banned) = self_.safe_lock(|p| {
+                (p.draining, p.bans.is_banned(address.ip(), Instant::now()))
+            })?;
+            if draining {
+                info!("Draining, refusing connection from {}", address);
+                continue;
+            }
+            if banned {
+                warn!("Refusing connection from banned address {}", address);
+                continue;
+            }
+            let slot = ConnectionLimiter::acquire(&connection_limiter, address.ip(), Instant::now());
+            let slot = match slot {
+                Ok(slot) => slot,
+                Err(e) => {
+                    warn!("Refusing connection from {}: {:?}", address, e);
+                    continue;
+                }
+            };
             debug!(
                 "New connection from {:?}",
                 stream.peer_addr().map_err(PoolError::Io)
@@ -465,7 +1002,8 @@ impl Pool {
                                 self_.clone(),
                                 receiver,
                                 sender,
-                                address
+                                address,
+                                slot
                             )
                             .await
                         );
@@ -484,6 +1022,7 @@ impl Pool {
         receiver: Receiver<EitherFrame>,
         sender: Sender<EitherFrame>,
         address: SocketAddr,
+        slot: ConnectionSlot,
     ) -> PoolResult<()> {
         let solution_sender = self_.safe_lock(|p| p.solution_sender.clone())?;
         let status_tx = self_.safe_lock(|s| s.status_tx.clone())?;
@@ -498,6 +1037,7 @@ impl Pool {
             // convert Listener variant to Downstream variant
             status_tx.listener_to_connection(),
             address,
+            slot,
         )
         .await?;
 
@@ -537,6 +1077,15 @@ impl Pool {
 
             match job_id {
                 Ok(job_id) => {
+                    // the jobs of the previous block are stale now, so their shares can no
+                    // longer be replayed and the hash cache only has to hold the new block
+                    let res = self_
+                        .safe_lock(|s| s.recent_share_hashes.safe_lock(|r| r.clear()))
+                        .map_err(|e| PoolError::PoisonLock(e.to_string()));
+                    let res = handle_result!(status_tx, res)
+                        .map_err(|e| PoolError::PoisonLock(e.to_string()));
+                    handle_result!(status_tx, res);
+
                     let downstreams = self_
                         .safe_lock(|s| s.downstreams.clone())
                         .map_err(|e| PoolError::PoisonLock(e.to_string()));
@@ -558,6 +1107,7 @@ impl Pool {
                         handle_result!(status_tx, res);
                     }
                     handle_result!(status_tx, sender_message_received_signal.send(()).await);
+                    Self::log_hashrates(&self_);
                 }
                 Err(_) => todo!(),
             }
@@ -572,6 +1122,8 @@ impl Pool {
     ) -> PoolResult<()> {
         let status_tx = self_.safe_lock(|s| s.status_tx.clone())?;
         let channel_factory = self_.safe_lock(|s| s.channel_factory.clone())?;
+        let (coinbase_outputs, coinbase_split) =
+            self_.safe_lock(|s| (s.coinbase_outputs.clone(), s.coinbase_split.clone()))?;
         while let Ok(mut new_template) = rx.recv().await {
             debug!(
                 "New template received, creating a new mining job(s): {:?}",
@@ -579,7 +1131,18 @@ impl Pool {
             );
 
             let messages = channel_factory
-                .safe_lock(|cf| cf.on_new_template(&mut new_template))
+                .safe_lock(|cf| {
+                    if let Some(split) = &coinbase_split {
+                        let mut outputs = coinbase_outputs.clone();
+                        split_coinbase_value(
+                            &mut outputs,
+                            split,
+                            new_template.coinbase_tx_value_remaining,
+                        );
+                        cf.update_pool_outputs(outputs);
+                    }
+                    cf.on_new_template(&mut new_template)
+                })
                 .map_err(|e| PoolError::PoisonLock(e.to_string()));
             let messages = handle_result!(status_tx, messages);
             let mut messages = handle_result!(status_tx, messages);
@@ -617,6 +1180,9 @@ impl Pool {
         sender_message_received_signal: Sender<()>,
         status_tx: status::Sender,
         mint: Arc<Mutex<Mint>>,
+        issuance_log: Option<Arc<Mutex<IssuanceLog>>>,
+        found_blocks: Arc<Mutex<FoundBlocks>>,
+        share_log: Option<Arc<Mutex<ShareLog>>>,
     ) -> Arc<Mutex<Self>> {
         let extranonce_len = 32;
         let range_0 = std::ops::Range { start: 0, end: 0 };
@@ -628,9 +1194,13 @@ impl Pool {
         let ids = Arc::new(Mutex::new(roles_logic_sv2::utils::GroupId::new()));
         let pool_coinbase_outputs = get_coinbase_output(&config);
         info!("PUB KEY: {:?}", pool_coinbase_outputs);
+        let pool_coinbase_outputs =
+            pool_coinbase_outputs.expect("Invalid coinbase output in config");
+        let coinbase_split =
+            get_coinbase_split(&config).expect("Invalid coinbase output split in config");
         let extranonces = ExtendedExtranonce::new(range_0, range_1, range_2);
         let creator = JobsCreators::new(extranonce_len as u8);
-        let share_per_min = 1.0;
+        let share_per_min = config.shares_per_minute;
         let kind = roles_logic_sv2::channel_logic::channel_factory::ExtendedChannelKind::Pool;
 
         // Clone `mint` to move into the blocking task
@@ -650,7 +1220,7 @@ impl Pool {
 
             keyset_result.unwrap() // Handle the result of safe_lock
         });
-        info!("KEYSET ID: {:}", keyset.id);
+        info!("KEYSET ID: {:}", keyset.keyset_id);
 
         let channel_factory = Arc::new(Mutex::new(PoolChannelFactory::new(
             ids,
@@ -658,7 +1228,7 @@ impl Pool {
             creator,
             share_per_min,
             kind,
-            pool_coinbase_outputs.expect("Invalid coinbase output in config"),
+            pool_coinbase_outputs.clone(),
             config.pool_signature.clone(),
             Arc::new(Mutex::new(keyset)),
         )));
@@ -670,6 +1240,33 @@ impl Pool {
             last_prev_hash_template_id: 0,
             status_tx: status_tx.clone(),
             mint: mint.clone(),
+            circuit_breaker: config.circuit_breaker.clone(),
+            review_queue: Arc::new(Mutex::new(ReviewQueue::new(
+                config.circuit_breaker.review_queue_capacity,
+            ))),
+            mint_config: config.mint.clone(),
+            issuance_log,
+            vardiff: config.vardiff.clone(),
+            shares_per_minute: config.shares_per_minute,
+            fixed_minimum_hashrate: config.fixed_minimum_hashrate,
+            found_blocks,
+            share_log,
+            coinbase_outputs: pool_coinbase_outputs,
+            coinbase_split,
+            misbehavior: config.misbehavior.clone(),
+            bans: BanList::new(Duration::from_secs(config.misbehavior.ban_duration_secs)),
+            custom_job_policy: config.custom_job_policy.clone(),
+            draining: false,
+            hashrate_window: Duration::from_secs(config.hashrate_window_secs),
+            rate_limit: config.rate_limit.clone(),
+            idle_timeout: match config.idle_timeout_secs {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
+            connection_limiter: Arc::new(Mutex::new(ConnectionLimiter::new(
+                config.connection_limit.clone(),
+            ))),
+            recent_share_hashes: Arc::new(Mutex::new(RecentShares::new(RECENT_SHARE_HASHES))),
         }));
 
         let cloned = pool.clone();
@@ -702,23 +1299,29 @@ impl Pool {
         }
 
         info!("Starting up pool listener");
-        let status_tx_clone = status_tx.clone();
-        task::spawn(async move {
-            if let Err(e) = Self::accept_incoming_connection(cloned, config).await {
-                error!("{}", e);
-            }
-            if status_tx_clone
-                .send(status::Status {
-                    state: status::State::DownstreamShutdown(PoolError::ComponentShutdown(
-                        "Downstream no longer accepting incoming connections".to_string(),
-                    )),
-                })
-                .await
-                .is_err()
-            {
-                error!("Downstream shutdown and Status Channel dropped");
-            }
-        });
+        let listeners = std::iter::once(ListenerConfig::new(config.listen_address.clone()))
+            .chain(config.extra_listeners.iter().cloned());
+        for listener in listeners {
+            let cloned = cloned.clone();
+            let config = config.clone();
+            let status_tx_clone = status_tx.clone();
+            task::spawn(async move {
+                if let Err(e) = Self::accept_incoming_connection(cloned, config, listener).await {
+                    error!("{}", e);
+                }
+                if status_tx_clone
+                    .send(status::Status {
+                        state: status::State::DownstreamShutdown(PoolError::ComponentShutdown(
+                            "Downstream no longer accepting incoming connections".to_string(),
+                        )),
+                    })
+                    .await
+                    .is_err()
+                {
+                    error!("Downstream shutdown and Status Channel dropped");
+                }
+            });
+        }
 
         let cloned = sender_message_received_signal.clone();
         let status_tx_clone = status_tx.clone();
@@ -771,13 +1374,123 @@ impl Pool {
     pub fn remove_downstream(&mut self, downstream_id: u32) {
         self.downstreams.remove(&downstream_id);
     }
+
+    /// Logs the estimated hashrate of every channel and of the whole pool, once per block.
+    fn log_hashrates(self_: &Arc<Mutex<Self>>) {
+        let downstreams = match self_.safe_lock(|s| s.downstreams.clone()) {
+            Ok(downstreams) => downstreams,
+            Err(e) => {
+                error!("Failed to lock pool: {}", e);
+                return;
+            }
+        };
+        let mut total = 0.0;
+        for (id, downstream) in downstreams {
+            let res = downstream.safe_lock(|d| (d.estimated_hashrates(), d.client().vendor.clone()));
+            let (hashrates, vendor) = match res {
+                Ok(res) => res,
+                Err(e) => {
+                    error!("Failed to lock downstream {}: {}", id, e);
+                    continue;
+                }
+            };
+            for (channel_id, hashrate) in hashrates {
+                info!(
+                    "Estimated hashrate of channel {} of downstream {} ({}): {}",
+                    channel_id,
+                    id,
+                    vendor,
+                    format_hashrate(hashrate)
+                );
+                total += hashrate;
+            }
+        }
+        info!("Estimated pool hashrate: {}", format_hashrate(total));
+    }
+
+    /// Stops accepting downstream connections, the connected downstreams keep being served.
+    pub fn start_draining(&mut self) {
+        self.draining = true;
+    }
+
+    /// Applies the operator decisions of the review file to the review queue of the issuance
+    /// circuit breaker, then logs the issuances still pending review. Rejected issuances are
+    /// discarded. Approved ones are signed and sent to their downstream in a late
+    /// `SubmitSharesSuccess`, the share having been answered with `ehash-diverted` when it was
+    /// diverted. Without a review file the queue is only logged.
+    pub async fn review_diverted_issuances(self_: Arc<Mutex<Self>>) -> PoolResult<()> {
+        let (review_file, review_queue) =
+            self_.safe_lock(|p| (p.circuit_breaker.review_file.clone(), p.review_queue.clone()))?;
+        let decisions = match &review_file {
+            Some(path) => match take_review_decisions(path) {
+                Ok(decisions) => decisions,
+                Err(e) => {
+                    error!("Failed to read review file {}: {}", path, e);
+                    vec![]
+                }
+            },
+            None => vec![],
+        };
+
+        let mut approved = vec![];
+        let pending = review_queue.safe_lock(|q| {
+            for decision in decisions {
+                let taken = q.take(decision.target);
+                if decision.approve {
+                    approved.extend(taken);
+                } else {
+                    for issuance in taken {
+                        warn!("Rejected diverted issuance: {}", issuance);
+                    }
+                }
+            }
+            for issuance in q.iter() {
+                info!("Diverted issuance pending review: {}", issuance);
+            }
+            q.len()
+        })?;
+        info!(
+            "{} diverted issuances approved, {} pending review",
+            approved.len(),
+            pending
+        );
+
+        for issuance in approved {
+            let downstream =
+                self_.safe_lock(|p| p.downstreams.get(&issuance.downstream_id).cloned())?;
+            let downstream = match downstream {
+                Some(downstream) => downstream,
+                None => {
+                    error!(
+                        "Downstream {} disconnected, approved issuance cannot be delivered: {}",
+                        issuance.downstream_id, issuance
+                    );
+                    continue;
+                }
+            };
+            match downstream.safe_lock(|d| d.sign_diverted(&issuance))? {
+                Ok(success) => {
+                    let sent =
+                        Downstream::send(downstream, Mining::SubmitSharesSuccess(success)).await;
+                    if let Err(e) = sent {
+                        error!("Failed to send approved issuance: {}: {}", issuance, e);
+                    }
+                }
+                Err(error_code) => error!(
+                    "Approved issuance refused by the mint checks with {}: {}",
+                    error_code, issuance
+                ),
+            }
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod test {
     use binary_sv2::{B0255, B064K};
     use ext_config::{Config, File, FileFormat};
-    use std::convert::TryInto;
+    use std::{convert::TryInto, net::IpAddr};
     use tracing::error;
 
     use stratum_common::{
@@ -785,7 +1498,7 @@ mod test {
         bitcoin::{util::psbt::serialize::Serialize, Transaction, Witness},
     };
 
-    use super::Configuration;
+    use super::{Configuration, ListenerConfig};
 
     // this test is used to verify the `coinbase_tx_prefix` and `coinbase_tx_suffix` values tested
     // against in message generator
@@ -880,6 +1593,59 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_mint_config_validation() {
+        assert!(super::MintConfig::default().validate().is_ok());
+
+        let zero_ttl = super::MintConfig {
+            mint_quote_ttl_secs: 0,
+            ..Default::default()
+        };
+        assert!(zero_ttl.validate().is_err());
+
+        let too_many_keys = super::MintConfig {
+            num_keys: 65,
+            ..Default::default()
+        };
+        assert!(too_many_keys.validate().is_err());
+
+        let unrepresentable = super::MintConfig {
+            num_keys: 4,
+            max_amount: 16,
+            ..Default::default()
+        };
+        assert!(unrepresentable.validate().is_err());
+    }
+
+    #[test]
+    fn test_listener_allowed_ips() {
+        let mut listener = ListenerConfig::new("127.0.0.1:34255".to_string());
+        let local: IpAddr = "127.0.0.1".parse().unwrap();
+        let remote: IpAddr = "10.0.0.1".parse().unwrap();
+        assert!(listener.allows(local) && listener.allows(remote));
+
+        listener.allowed_ips = vec![local];
+        assert!(listener.allows(local));
+        assert!(!listener.allows(remote));
+    }
+
+    #[test]
+    fn test_coinbase_split() {
+        let script = bitcoin::Script::new();
+        let mut outputs = vec![
+            bitcoin::TxOut {
+                value: 0,
+                script_pubkey: script.clone(),
+            };
+            3
+        ];
+        super::split_coinbase_value(&mut outputs, &[90.0, 7.5, 2.5], 625_000_001);
+        let values: Vec<u64> = outputs.iter().map(|o| o.value).collect();
+        // the rounding dust of the other outputs goes to the first one
+        assert_eq!(values, vec![562_500_001, 46_875_000, 15_625_000]);
+        assert_eq!(values.iter().sum::<u64>(), 625_000_001);
+    }
+
     // copied from roles-logic-sv2::job_creator
     fn coinbase_tx_prefix(coinbase: &Transaction, script_prefix_len: usize) -> B064K<'static> {
         let encoded = coinbase.serialize();
@@ -0,0 +1,94 @@
+use serde::Deserialize;
+use std::time::Instant;
+
+/// Settings of the inbound message limiter of downstream connections. Every frame takes a token
+/// from a bucket holding up to `burst` tokens and refilled at `messages_per_second`, a
+/// downstream sending a frame on an empty bucket is disconnected.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    pub messages_per_second: f64,
+    pub burst: f64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            messages_per_second: 100.0,
+            burst: 500.0,
+        }
+    }
+}
+
+/// Token bucket of a single downstream connection.
+#[derive(Debug)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig, now: Instant) -> Self {
+        let tokens = config.burst;
+        Self {
+            config,
+            tokens,
+            updated_at: now,
+        }
+    }
+
+    /// Takes a token for a frame received at `now`, returns false when the bucket is empty.
+    pub fn allow(&mut self, now: Instant) -> bool {
+        if !self.config.enabled {
+            return true;
+        }
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens =
+            (self.tokens + elapsed * self.config.messages_per_second).min(self.config.burst);
+        self.updated_at = now;
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn allows_bursts_and_refills_over_time() {
+        let start = Instant::now();
+        let config = RateLimitConfig {
+            enabled: true,
+            messages_per_second: 10.0,
+            burst: 5.0,
+        };
+        let mut limiter = RateLimiter::new(config, start);
+
+        for _ in 0..5 {
+            assert!(limiter.allow(start));
+        }
+        assert!(!limiter.allow(start));
+
+        // 10 messages per second refill a token every 100ms, never above the burst
+        assert!(limiter.allow(start + Duration::from_millis(100)));
+        assert!(!limiter.allow(start + Duration::from_millis(100)));
+        let later = start + Duration::from_secs(60);
+        for _ in 0..5 {
+            assert!(limiter.allow(later));
+        }
+        assert!(!limiter.allow(later));
+
+        let mut disabled = RateLimiter::new(RateLimitConfig::default(), start);
+        for _ in 0..1000 {
+            assert!(disabled.allow(start));
+        }
+    }
+}
@@ -15,23 +15,58 @@ use roles_logic_sv2::{
     routing_logic::{CommonRoutingLogic, NoRouting},
     utils::Mutex,
 };
-use std::{convert::TryInto, net::SocketAddr, sync::Arc};
+use std::{convert::TryInto, fmt, net::SocketAddr, sync::Arc};
 use tracing::{debug, error};
 
+/// Software and device a downstream announced in its `SetupConnection`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientInfo {
+    pub vendor: String,
+    pub hardware_version: String,
+    pub firmware: String,
+    pub device_id: String,
+}
+
+impl ClientInfo {
+    fn from_setup_connection(m: &SetupConnection) -> Self {
+        let string = |field: Vec<u8>| String::from_utf8_lossy(&field).into_owned();
+        Self {
+            vendor: string(m.vendor.to_vec()),
+            hardware_version: string(m.hardware_version.to_vec()),
+            firmware: string(m.firmware.to_vec()),
+            device_id: string(m.device_id.to_vec()),
+        }
+    }
+}
+
+impl fmt::Display for ClientInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "vendor {:?}, hardware {:?}, firmware {:?}, device {:?}",
+            self.vendor, self.hardware_version, self.firmware, self.device_id
+        )
+    }
+}
+
 pub struct SetupConnectionHandler {
     header_only: Option<bool>,
+    client: ClientInfo,
 }
 
 impl SetupConnectionHandler {
     pub fn new() -> Self {
-        Self { header_only: None }
+        Self {
+            header_only: None,
+            client: ClientInfo::default(),
+        }
     }
     pub async fn setup(
         self_: Arc<Mutex<Self>>,
         receiver: &mut Receiver<EitherFrame>,
         sender: &mut Sender<EitherFrame>,
         address: SocketAddr,
-    ) -> PoolResult<CommonDownstreamData> {
+    ) -> PoolResult<(CommonDownstreamData, ClientInfo)> {
         // read stdFrame from receiver
 
         let mut incoming: StdFrame = match receiver.recv().await {
@@ -71,16 +106,17 @@ impl SetupConnectionHandler {
         let sv2_frame: StdFrame = PoolMessages::Common(message.clone()).try_into()?;
         let sv2_frame = sv2_frame.into();
         sender.send(sv2_frame).await?;
-        self_.safe_lock(|s| s.header_only)?;
+        let client = self_.safe_lock(|s| s.client.clone())?;
 
         match message {
             CommonMessages::SetupConnectionSuccess(m) => {
                 debug!("Sent back SetupConnectionSuccess: {:?}", m);
-                Ok(CommonDownstreamData {
+                let downstream_data = CommonDownstreamData {
                     header_only: has_requires_std_job(m.flags),
                     work_selection: has_work_selection(m.flags),
                     version_rolling: has_version_rolling(m.flags),
-                })
+                };
+                Ok((downstream_data, client))
             }
             _ => panic!(),
         }
@@ -97,6 +133,7 @@ impl ParseDownstreamCommonMessages<NoRouting> for SetupConnectionHandler {
         let header_only = incoming.requires_standard_job();
         debug!("Handling setup connection: header_only: {}", header_only);
         self.header_only = Some(header_only);
+        self.client = ClientInfo::from_setup_connection(&incoming);
         Ok(SendTo::RelayNewMessageToRemote(
             Arc::new(Mutex::new(())),
             CommonMessages::SetupConnectionSuccess(SetupConnectionSuccess {
@@ -0,0 +1,105 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    hash::Hash,
+};
+
+/// Maximum number of share hashes remembered by the pool to detect shares replayed across
+/// channels and connections.
+///
+/// Replays are keyed by share hash rather than by the last sequence number acknowledged on a
+/// channel. A reconnecting translator opens a new channel, so the sequence numbers of the old
+/// one mean nothing there, and the translator sends every share with sequence number 0. A share
+/// can only be replayed while its job is valid, which ends with the next block or a pool
+/// restart, so the cache is kept in memory and cleared on every new prev hash. The capacity only
+/// bounds its memory: at the default of 1 share per minute it holds an hour long block of a
+/// thousand channels, past that the oldest hashes of the block are forgotten and could be
+/// replayed.
+pub const RECENT_SHARE_HASHES: usize = 65536;
+
+/// Fields that make a share unique within a channel.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ShareKey {
+    pub job_id: u32,
+    pub nonce: u32,
+    pub ntime: u32,
+    pub version: u32,
+    pub extranonce: Vec<u8>,
+}
+
+/// Bounded set of the most recent shares of a channel, used to reject resubmitted shares before
+/// their blinded messages are signed a second time. Keyed by share hash it also catches shares
+/// replayed on another channel or connection.
+#[derive(Debug)]
+pub struct RecentShares<K = ShareKey> {
+    capacity: usize,
+    order: VecDeque<K>,
+    seen: HashSet<K>,
+}
+
+impl<K: Clone + Eq + Hash> RecentShares<K> {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            capacity,
+            order: VecDeque::with_capacity(capacity),
+            seen: HashSet::with_capacity(capacity),
+        }
+    }
+
+    /// Whether a share was already recorded.
+    pub fn contains(&self, key: &K) -> bool {
+        self.seen.contains(key)
+    }
+
+    /// Records a share, returning `false` if it was already seen.
+    pub fn insert(&mut self, key: K) -> bool {
+        if self.seen.contains(&key) {
+            return false;
+        }
+        if self.order.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        self.seen.insert(key.clone());
+        self.order.push_back(key);
+        true
+    }
+
+    /// Forgets every recorded share.
+    pub fn clear(&mut self) {
+        self.order.clear();
+        self.seen.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key(nonce: u32) -> ShareKey {
+        ShareKey {
+            job_id: 1,
+            nonce,
+            ntime: 2,
+            version: 3,
+            extranonce: vec![0, 1, 2, 3],
+        }
+    }
+
+    #[test]
+    fn rejects_duplicates_and_forgets_oldest() {
+        let mut shares = RecentShares::new(2);
+        assert!(shares.insert(key(0)));
+        assert!(!shares.insert(key(0)));
+        assert!(shares.insert(key(1)));
+        assert!(shares.insert(key(2)));
+        // key(0) was evicted to make room for key(2)
+        assert!(shares.insert(key(0)));
+        assert!(!shares.insert(key(2)));
+
+        shares.clear();
+        assert!(!shares.contains(&key(2)));
+        assert!(shares.insert(key(2)));
+    }
+}
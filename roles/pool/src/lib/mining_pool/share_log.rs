@@ -0,0 +1,205 @@
+use bitcoin::hex::DisplayHex;
+use serde::Deserialize;
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Settings of the accepted share log. Once the active file grows past `max_bytes` it is renamed
+/// to `<path>.1`, older files are shifted up to `<path>.<max_files>` and the oldest is dropped.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ShareLogConfig {
+    /// File accepted shares are appended to, disabled if unset
+    pub path: Option<String>,
+    pub max_bytes: u64,
+    /// Number of rotated files kept next to the active one
+    pub max_files: usize,
+}
+
+impl Default for ShareLogConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            max_bytes: 64 * 1024 * 1024,
+            max_files: 5,
+        }
+    }
+}
+
+/// What the mint did with the blinded messages of an accepted share.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShareOutcome {
+    /// Standard shares carry no blinded messages
+    Unissued,
+    /// Ehash amount signed by the mint
+    Issued(u64),
+    /// Refused by the max amount or share work checks
+    Refused,
+    /// Parked in the review queue by the circuit breaker
+    Diverted,
+}
+
+impl fmt::Display for ShareOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShareOutcome::Unissued => write!(f, "unissued"),
+            ShareOutcome::Issued(amount) => write!(f, "issued:{}", amount),
+            ShareOutcome::Refused => write!(f, "refused"),
+            ShareOutcome::Diverted => write!(f, "diverted"),
+        }
+    }
+}
+
+/// Accepted share, as written to the share log.
+#[derive(Debug, Clone)]
+pub struct ShareRecord {
+    pub timestamp: u64,
+    pub downstream_id: u32,
+    pub channel_id: u32,
+    pub sequence_number: u32,
+    pub hash: [u8; 32],
+    /// Leading zero bits of the share hash
+    pub work: u64,
+    pub outcome: ShareOutcome,
+}
+
+impl ShareRecord {
+    pub fn now(
+        downstream_id: u32,
+        channel_id: u32,
+        sequence_number: u32,
+        hash: [u8; 32],
+        work: u64,
+        outcome: ShareOutcome,
+    ) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        Self {
+            timestamp,
+            downstream_id,
+            channel_id,
+            sequence_number,
+            hash,
+            work,
+            outcome,
+        }
+    }
+}
+
+impl fmt::Display for ShareRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {} {} {}",
+            self.timestamp,
+            self.downstream_id,
+            self.channel_id,
+            self.sequence_number,
+            self.hash.to_lower_hex_string(),
+            self.work,
+            self.outcome
+        )
+    }
+}
+
+/// Append-only log of every share accepted by the pool, for payout and dispute audits.
+#[derive(Debug)]
+pub struct ShareLog {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    file: File,
+    written: u64,
+}
+
+impl ShareLog {
+    pub fn open(path: &str, config: &ShareLogConfig) -> io::Result<Self> {
+        let path = PathBuf::from(path);
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let written = file.metadata()?.len();
+        Ok(Self {
+            path,
+            max_bytes: config.max_bytes,
+            max_files: config.max_files,
+            file,
+            written,
+        })
+    }
+
+    pub fn append(&mut self, record: &ShareRecord) -> io::Result<()> {
+        let line = format!("{}\n", record);
+        if self.written > 0 && self.written + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotated(&self, index: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", index));
+        name.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for index in (1..self.max_files).rev() {
+                let from = self.rotated(index);
+                if from.exists() {
+                    fs::rename(&from, self.rotated(index + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated(1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rotates_and_keeps_max_files() {
+        let dir = std::env::temp_dir().join(format!("share-log-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("shares.log");
+
+        let record = ShareRecord::now(1, 2, 3, [0xab; 32], 8, ShareOutcome::Issued(8));
+        let line_len = format!("{}\n", record).len() as u64;
+        let config = ShareLogConfig {
+            path: None,
+            max_bytes: line_len * 2,
+            max_files: 2,
+        };
+        let mut log = ShareLog::open(path.to_str().unwrap(), &config).unwrap();
+        for _ in 0..7 {
+            log.append(&record).unwrap();
+        }
+
+        // 7 lines, 2 per file: the active file holds 1 and only 2 rotated files are kept
+        let lines = |p: PathBuf| fs::read_to_string(p).unwrap().lines().count();
+        assert_eq!(lines(path.clone()), 1);
+        assert_eq!(lines(dir.join("shares.log.1")), 2);
+        assert_eq!(lines(dir.join("shares.log.2")), 2);
+        assert!(!dir.join("shares.log.3").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,137 @@
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+/// Settings for the per-channel variable difficulty loop. Each channel starts at the target
+/// derived from the nominal hashrate it advertised; once per `retarget_interval_secs` the
+/// observed share rate is compared with the pool's shares-per-minute target and, when it
+/// falls outside the `tolerance` band, the channel hashrate is re-estimated and a new target
+/// is sent with `SetTarget`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct VardiffConfig {
+    pub enabled: bool,
+    /// Minimum number of seconds between two retargets of a channel
+    pub retarget_interval_secs: u64,
+    /// Accepted relative deviation from the target share rate, 0.5 allows 50% above or below
+    pub tolerance: f32,
+    /// Largest factor the estimated hashrate can change by in a single retarget
+    pub max_adjustment: f32,
+}
+
+impl Default for VardiffConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            retarget_interval_secs: 120,
+            tolerance: 0.5,
+            max_adjustment: 4.0,
+        }
+    }
+}
+
+/// Share rate tracking for one channel.
+#[derive(Debug)]
+pub struct ChannelVardiff {
+    config: VardiffConfig,
+    shares_per_minute: f32,
+    hashrate: f32,
+    window_start: Instant,
+    shares: u32,
+}
+
+impl ChannelVardiff {
+    pub fn new(
+        config: VardiffConfig,
+        shares_per_minute: f32,
+        hashrate: f32,
+        now: Instant,
+    ) -> Self {
+        Self {
+            config,
+            shares_per_minute,
+            hashrate,
+            window_start: now,
+            shares: 0,
+        }
+    }
+
+    /// Hashrate the current channel target was derived from.
+    pub fn hashrate(&self) -> f32 {
+        self.hashrate
+    }
+
+    /// Restarts the measurement from a hashrate announced by the downstream.
+    pub fn reset(&mut self, hashrate: f32, now: Instant) {
+        self.hashrate = hashrate;
+        self.window_start = now;
+        self.shares = 0;
+    }
+
+    /// Records an accepted share and returns the new estimated hashrate of the channel when its
+    /// target has to be updated.
+    pub fn on_accepted_share(&mut self, now: Instant) -> Option<f32> {
+        if !self.config.enabled {
+            return None;
+        }
+        self.shares += 1;
+        let elapsed = now.duration_since(self.window_start);
+        if elapsed < Duration::from_secs(self.config.retarget_interval_secs.max(1)) {
+            return None;
+        }
+
+        let observed = self.shares as f32 / (elapsed.as_secs_f32() / 60.0);
+        let ratio = observed / self.shares_per_minute;
+        self.window_start = now;
+        self.shares = 0;
+        if (ratio - 1.0).abs() <= self.config.tolerance {
+            return None;
+        }
+
+        let max = self.config.max_adjustment.max(1.0);
+        self.hashrate *= ratio.clamp(1.0 / max, max);
+        Some(self.hashrate)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config() -> VardiffConfig {
+        VardiffConfig {
+            enabled: true,
+            retarget_interval_secs: 60,
+            tolerance: 0.5,
+            max_adjustment: 4.0,
+        }
+    }
+
+    #[test]
+    fn retargets_outside_of_the_band() {
+        let start = Instant::now();
+        let mut vardiff = ChannelVardiff::new(config(), 1.0, 100.0, start);
+
+        // 1 share in a minute matches the target rate of 1
+        assert_eq!(vardiff.on_accepted_share(start + Duration::from_secs(60)), None);
+
+        // 10 shares in the next minute, the adjustment is capped at 4x
+        let next = start + Duration::from_secs(60);
+        for i in 1..10 {
+            assert_eq!(vardiff.on_accepted_share(next + Duration::from_secs(i)), None);
+        }
+        assert_eq!(
+            vardiff.on_accepted_share(next + Duration::from_secs(60)),
+            Some(400.0)
+        );
+    }
+
+    #[test]
+    fn disabled_never_retargets() {
+        let start = Instant::now();
+        let mut vardiff = ChannelVardiff::new(VardiffConfig::default(), 1.0, 100.0, start);
+        for i in 0..100 {
+            let now = start + Duration::from_secs(i * 10);
+            assert_eq!(vardiff.on_accepted_share(now), None);
+        }
+    }
+}
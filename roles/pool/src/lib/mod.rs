@@ -8,11 +8,14 @@ use std::{collections::HashMap, convert::TryInto, net::SocketAddr, sync::Arc};
 use async_channel::{bounded, unbounded};
 
 use error::PoolError;
-use mining_pool::{get_coinbase_output, Configuration, Pool};
+use mining_pool::{
+    found_blocks::FoundBlocks, get_coinbase_output, get_coinbase_split,
+    issuance_log::IssuanceLog, share_log::ShareLog, Configuration, Pool,
+};
 use mining_sv2::cashu::Sv2KeySet;
 use roles_logic_sv2::utils::Mutex;
 use template_receiver::TemplateRx;
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
 
 use tokio::select;
 use cdk::{cdk_database::mint_memory::MintMemoryDatabase, nuts::{CurrencyUnit, MintInfo, Nuts}, Mint, types::QuoteTTL};
@@ -24,13 +27,13 @@ pub const HASH_CURRENCY_UNIT: &str = "HASH";
 pub const HASH_DERIVATION_PATH: u32 = 1337;
 
 #[derive(Clone)]
-pub struct PoolSv2<'decoder> {
+pub struct PoolSv2 {
     config: Configuration,
-    keyset: Option<Arc<Mutex<Sv2KeySet<'decoder>>>>,
+    keyset: Option<Arc<Mutex<Sv2KeySet>>>,
 }
 
 // TODO remove after porting mint to use Sv2 data types
-impl<'decoder> std::fmt::Debug for PoolSv2<'decoder> {
+impl std::fmt::Debug for PoolSv2 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("PoolSv2")
             .field("config", &self.config)
@@ -40,8 +43,59 @@ impl<'decoder> std::fmt::Debug for PoolSv2<'decoder> {
     }
 }
 
-impl PoolSv2<'_> {
-    pub fn new(config: Configuration) -> PoolSv2<'static> {
+/// Resolves when the operator asks for the pool to be drained, with a SIGTERM.
+#[cfg(unix)]
+async fn drain_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+    match signal(SignalKind::terminate()) {
+        Ok(mut sigterm) => {
+            sigterm.recv().await;
+        }
+        Err(e) => {
+            error!("Unable to listen for SIGTERM, drain mode unavailable: {}", e);
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn drain_signal() {
+    std::future::pending::<()>().await;
+}
+
+/// Reviews the diverted issuances of the issuance circuit breaker every time the operator sends a
+/// SIGUSR1.
+#[cfg(unix)]
+fn spawn_diverted_issuance_review(pool: Arc<Mutex<Pool>>) {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut sigusr1 = match signal(SignalKind::user_defined1()) {
+        Ok(sigusr1) => sigusr1,
+        Err(e) => {
+            error!("Unable to listen for SIGUSR1, diverted issuances cannot be reviewed: {}", e);
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        while sigusr1.recv().await.is_some() {
+            if let Err(e) = Pool::review_diverted_issuances(pool.clone()).await {
+                error!("Failed to review diverted issuances: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+fn spawn_diverted_issuance_review(_pool: Arc<Mutex<Pool>>) {}
+
+async fn drain_deadline_reached(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending::<()>().await,
+    }
+}
+
+impl PoolSv2 {
+    pub fn new(config: Configuration) -> PoolSv2 {
         PoolSv2 {
             config,
             keyset: None,
@@ -55,21 +109,50 @@ impl PoolSv2<'_> {
         let (s_prev_hash, r_prev_hash) = bounded(10);
         let (s_solution, r_solution) = bounded(10);
         let (s_message_recv_signal, r_message_recv_signal) = bounded(10);
+        config.mint.validate().map_err(PoolError::Custom)?;
+        get_coinbase_split(&config).map_err(PoolError::Custom)?;
+        if !(config.shares_per_minute > 0.0) {
+            return Err(PoolError::Custom(format!(
+                "shares_per_minute must be positive, got {}",
+                config.shares_per_minute
+            )));
+        }
+        let issuance_log = match &config.mint.issuance_log_path {
+            Some(path) => Some(Arc::new(Mutex::new(IssuanceLog::open(path)?))),
+            None => None,
+        };
+        let found_blocks = Arc::new(Mutex::new(FoundBlocks::new(
+            config.found_blocks_path.as_ref(),
+        )?));
+        let share_log = match &config.share_log.path {
+            Some(path) => Some(Arc::new(Mutex::new(ShareLog::open(path, &config.share_log)?))),
+            None => None,
+        };
         let coinbase_output_result = get_coinbase_output(&config);
         let coinbase_output_len = coinbase_output_result?.len() as u32;
         let tp_authority_public_key = config.tp_authority_public_key;
-        let tp_address: SocketAddr = config.tp_address.parse().unwrap();
-        
-        // Debugging information
-        dbg!(&tp_address, &tp_authority_public_key, &coinbase_output_len);
+        let tp_addresses = std::iter::once(&config.tp_address)
+            .chain(&config.tp_fallback_addresses)
+            .map(|address| {
+                address.parse::<SocketAddr>().map_err(|e| {
+                    PoolError::Custom(format!("Invalid template provider address {}: {}", address, e))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        debug!(
+            "Template providers {:?}, authority key {:?}, coinbase output length {}",
+            tp_addresses, tp_authority_public_key, coinbase_output_len
+        );
 
         let template_rx_res = TemplateRx::connect(
-            config.tp_address.parse().unwrap(),
+            tp_addresses,
+            std::time::Duration::from_secs(config.tp_failback_interval_secs),
+            std::time::Duration::from_secs(config.tp_reconnect_max_backoff_secs.max(1)),
             s_new_t,
             s_prev_hash,
             r_solution,
             r_message_recv_signal,
-            status::Sender::Upstream(status_tx.clone()),
             coinbase_output_len,
             tp_authority_public_key,
         )
@@ -94,13 +177,36 @@ impl PoolSv2<'_> {
             s_message_recv_signal,
             status::Sender::DownstreamListener(status_tx),
             mint.unwrap().clone(),
+            issuance_log,
+            found_blocks,
+            share_log,
         );
+        spawn_diverted_issuance_review(pool.clone());
+
+        let drain_period = std::time::Duration::from_secs(config.drain_period_secs);
+        let mut drain_deadline: Option<tokio::time::Instant> = None;
+        let mut drain_requested = Box::pin(drain_signal());
 
         // Start the error handling loop
         // See `./status.rs` and `utils/error_handling` for information on how this operates
         loop {
             let task_status = select! {
                 task_status = status_rx.recv() => task_status,
+                _ = &mut drain_requested, if drain_deadline.is_none() => {
+                    info!(
+                        "Drain requested, refusing new downstreams and shutting down in {:?}",
+                        drain_period
+                    );
+                    if pool.safe_lock(|p| p.start_draining()).is_err() {
+                        break Ok(());
+                    }
+                    drain_deadline = Some(tokio::time::Instant::now() + drain_period);
+                    continue;
+                }
+                _ = drain_deadline_reached(drain_deadline) => {
+                    info!("Drain period over, shutting down");
+                    break Ok(());
+                }
                 interrupt_signal = tokio::signal::ctrl_c() => {
                     match interrupt_signal {
                         Ok(()) => {
@@ -125,9 +231,10 @@ impl PoolSv2<'_> {
                     );
                     break Ok(());
                 }
+                // template provider errors only go to the connection status channel of the
+                // template receiver, which reconnects instead of reporting them here
                 status::State::TemplateProviderShutdown(err) => {
-                    error!("SHUTDOWN from Upstream: {}\nTry to reconnecting or connecting to a new upstream", err);
-                    break Ok(());
+                    warn!("Unexpected template provider error on the pool status channel: {}", err);
                 }
                 status::State::Healthy(msg) => {
                     info!("HEALTHY message: {}", msg);
@@ -146,7 +253,7 @@ impl PoolSv2<'_> {
     }
 
     async fn create_mint(&self) -> Mint {
-        const NUM_KEYS: u8 = 64;
+        let num_keys = self.config.mint.num_keys;
 
         let nuts = Nuts::new().nut07(true);
 
@@ -158,7 +265,7 @@ impl PoolSv2<'_> {
         let hash_currency_unit = CurrencyUnit::Custom(HASH_CURRENCY_UNIT.to_string());
 
         let mut currency_units = HashMap::new();
-        currency_units.insert(hash_currency_unit.clone(), (0, NUM_KEYS));
+        currency_units.insert(hash_currency_unit.clone(), (0, num_keys));
 
         let mut derivation_paths = HashMap::new();
         derivation_paths.insert(hash_currency_unit, DerivationPath::from(vec![
@@ -172,7 +279,10 @@ impl PoolSv2<'_> {
             "http://localhost:8000",
             &mnemonic.to_seed_normalized(""),
             mint_info,
-            QuoteTTL::new(1000, 1000),
+            QuoteTTL::new(
+                self.config.mint.mint_quote_ttl_secs,
+                self.config.mint.melt_quote_ttl_secs,
+            ),
             Arc::new(MintMemoryDatabase::default()),
             HashMap::new(),
             currency_units,
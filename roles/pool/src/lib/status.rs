@@ -45,6 +45,7 @@ impl Clone for Sender {
 #[derive(Debug)]
 pub enum State {
     DownstreamShutdown(PoolError),
+    /// Lost connection to the template provider, only sent to the template receiver
     TemplateProviderShutdown(PoolError),
     DownstreamInstanceDropped(u32),
     Healthy(String),
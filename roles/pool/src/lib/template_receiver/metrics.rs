@@ -0,0 +1,90 @@
+use std::{fmt, time::Duration};
+
+/// Time spent between receiving a template distribution message from the TP and the mining pool
+/// signaling that the resulting jobs were broadcast to every downstream.
+#[derive(Debug, Default, Clone)]
+pub struct LatencyStats {
+    pub count: u64,
+    pub total: Duration,
+    pub max: Duration,
+    pub last: Duration,
+}
+
+impl LatencyStats {
+    pub fn record(&mut self, elapsed: Duration) {
+        self.count += 1;
+        self.total += elapsed;
+        self.last = elapsed;
+        if elapsed > self.max {
+            self.max = elapsed;
+        }
+    }
+
+    pub fn mean(&self) -> Duration {
+        match self.count {
+            0 => Duration::ZERO,
+            n => self.total / n as u32,
+        }
+    }
+}
+
+impl fmt::Display for LatencyStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "count {}, last {:?}, mean {:?}, max {:?}",
+            self.count,
+            self.last,
+            self.mean(),
+            self.max
+        )
+    }
+}
+
+/// Counters for the messages received from the template provider.
+#[derive(Debug, Default, Clone)]
+pub struct TemplateMetrics {
+    pub new_template: LatencyStats,
+    pub new_prev_hash: LatencyStats,
+    pub future_templates: u64,
+    pub template_bytes_total: u64,
+    pub template_bytes_max: usize,
+}
+
+impl TemplateMetrics {
+    pub fn on_new_template(&mut self, payload_len: usize, future: bool, elapsed: Duration) {
+        self.new_template.record(elapsed);
+        self.template_bytes_total += payload_len as u64;
+        if payload_len > self.template_bytes_max {
+            self.template_bytes_max = payload_len;
+        }
+        if future {
+            self.future_templates += 1;
+        }
+    }
+
+    pub fn on_new_prev_hash(&mut self, elapsed: Duration) {
+        self.new_prev_hash.record(elapsed);
+    }
+
+    pub fn mean_template_bytes(&self) -> u64 {
+        match self.new_template.count {
+            0 => 0,
+            n => self.template_bytes_total / n,
+        }
+    }
+}
+
+impl fmt::Display for TemplateMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "NewTemplate to job broadcast: {} ({} future); SetNewPrevHash to job broadcast: {}; template size: mean {} bytes, max {} bytes",
+            self.new_template,
+            self.future_templates,
+            self.new_prev_hash,
+            self.mean_template_bytes(),
+            self.template_bytes_max
+        )
+    }
+}
@@ -3,7 +3,7 @@ use super::{
     mining_pool::{EitherFrame, StdFrame},
     status,
 };
-use async_channel::{Receiver, Sender};
+use async_channel::{unbounded, Receiver, Sender};
 use codec_sv2::{HandshakeRole, Initiator};
 use error_handling::handle_result;
 use key_utils::Secp256k1PublicKey;
@@ -16,12 +16,19 @@ use roles_logic_sv2::{
     },
     utils::Mutex,
 };
-use std::{convert::TryInto, net::SocketAddr, sync::Arc};
-use tokio::{net::TcpStream, task};
-use tracing::info;
+use std::{
+    convert::TryInto,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{net::TcpStream, select, sync::watch, task};
+use tracing::{debug, error, info, warn};
 
 mod message_handler;
+pub mod metrics;
 mod setup_connection;
+use metrics::TemplateMetrics;
 use setup_connection::SetupConnectionHandler;
 
 pub struct TemplateRx {
@@ -33,46 +40,226 @@ pub struct TemplateRx {
     status_tx: status::Sender,
 }
 
+/// Everything needed to open a connection to one of the template providers.
+#[derive(Clone)]
+struct TpConnector {
+    templ_sender: Sender<NewTemplate<'static>>,
+    prev_h_sender: Sender<SetNewPrevHash<'static>>,
+    solution_receiver: Receiver<SubmitSolution<'static>>,
+    message_received_signal: Receiver<()>,
+    coinbase_out_len: u32,
+    expected_tp_authority_public_key: Option<Secp256k1PublicKey>,
+}
+
+/// Live connection to a template provider.
+struct TpConnection {
+    index: usize,
+    template_rx: Arc<Mutex<TemplateRx>>,
+    /// Noise reader and writer plus the solution forwarding task
+    tasks: Vec<task::AbortHandle>,
+}
+
 impl TemplateRx {
+    /// Connects to the first reachable template provider of `addresses`, the first address
+    /// being the primary one. When the connection drops the pool fails over to the next
+    /// reachable address, and while it is not on the primary the primary is probed every
+    /// `failback_interval` so the pool can fail back to it. Once none of the template providers
+    /// can be reached they are retried with an exponential backoff capped at `max_backoff`, the
+    /// downstreams staying connected meanwhile. Only the initial connection can fail.
     #[allow(clippy::too_many_arguments)]
     pub async fn connect(
-        address: SocketAddr,
+        addresses: Vec<SocketAddr>,
+        failback_interval: Duration,
+        max_backoff: Duration,
         templ_sender: Sender<NewTemplate<'static>>,
         prev_h_sender: Sender<SetNewPrevHash<'static>>,
         solution_receiver: Receiver<SubmitSolution<'static>>,
         message_received_signal: Receiver<()>,
-        status_tx: status::Sender,
         coinbase_out_len: u32,
         expected_tp_authority_public_key: Option<Secp256k1PublicKey>,
     ) -> PoolResult<()> {
+        let connector = TpConnector {
+            templ_sender,
+            prev_h_sender,
+            solution_receiver,
+            message_received_signal,
+            coinbase_out_len,
+            expected_tp_authority_public_key,
+        };
+        // errors of the active connection are reported here instead of the main status loop,
+        // so they trigger a failover rather than a shutdown
+        let (connection_status_tx, connection_status_rx) = unbounded();
+        let connection_status_tx = status::Sender::Upstream(connection_status_tx);
+        let connection =
+            Self::connect_any(&connector, &addresses, connection_status_tx.clone()).await?;
+        task::spawn(Self::supervise(
+            connector,
+            addresses,
+            failback_interval,
+            max_backoff,
+            connection,
+            connection_status_tx,
+            connection_status_rx,
+        ));
+        Ok(())
+    }
+
+    /// Runs the active connection, failing over when it drops and failing back to the primary
+    /// template provider once it is reachable again.
+    async fn supervise(
+        connector: TpConnector,
+        addresses: Vec<SocketAddr>,
+        failback_interval: Duration,
+        max_backoff: Duration,
+        mut connection: TpConnection,
+        connection_status_tx: status::Sender,
+        connection_status_rx: Receiver<status::Status>,
+    ) {
+        loop {
+            let on_fallback = connection.index != 0;
+            // `start` is told to stop instead of being dropped, so it never leaves the pool's
+            // acknowledgment of a forwarded template or prev hash for the next connection
+            let (failback_tx, failback_rx) = watch::channel(false);
+            let probe = async {
+                if on_fallback {
+                    Self::wait_until_reachable(addresses[0], failback_interval).await;
+                    let _ = failback_tx.send(true);
+                }
+                std::future::pending::<()>().await
+            };
+            select! {
+                _ = Self::start(connection.template_rx.clone(), failback_rx.clone()) => (),
+                _ = probe => (),
+            }
+            let primary_back = *failback_rx.borrow();
+            for task in &connection.tasks {
+                task.abort();
+            }
+            while let Ok(s) = connection_status_rx.try_recv() {
+                warn!(
+                    "Template provider {}: {:?}",
+                    addresses[connection.index], s.state
+                );
+            }
+            if primary_back {
+                info!(
+                    "Primary template provider {} is reachable again, failing back",
+                    addresses[0]
+                );
+            } else {
+                warn!(
+                    "Lost connection to template provider {}",
+                    addresses[connection.index]
+                );
+            }
+            drop(connection);
+            // `start` only returns between messages, so an acknowledgment still queued here
+            // belongs to no message of the next connection
+            while connector.message_received_signal.try_recv().is_ok() {}
+
+            connection = Self::reconnect(
+                &connector,
+                &addresses,
+                connection_status_tx.clone(),
+                max_backoff,
+            )
+            .await;
+        }
+    }
+
+    /// Retries every template provider until one of them accepts the connection, doubling the
+    /// wait after each failed round up to `max_backoff`.
+    async fn reconnect(
+        connector: &TpConnector,
+        addresses: &[SocketAddr],
+        status_tx: status::Sender,
+        max_backoff: Duration,
+    ) -> TpConnection {
+        let mut backoff = Duration::from_secs(1).min(max_backoff);
+        loop {
+            match Self::connect_any(connector, addresses, status_tx.clone()).await {
+                Ok(connection) => return connection,
+                Err(e) => {
+                    error!(
+                        "No template provider reachable, retrying in {:?}: {}",
+                        backoff, e
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(max_backoff);
+                }
+            }
+        }
+    }
+
+    /// Connects to the first reachable address, in order.
+    async fn connect_any(
+        connector: &TpConnector,
+        addresses: &[SocketAddr],
+        status_tx: status::Sender,
+    ) -> PoolResult<TpConnection> {
+        let mut last_error = PoolError::Custom("No template provider configured".to_string());
+        for (index, address) in addresses.iter().enumerate() {
+            match Self::connect_to(connector, *address, status_tx.clone()).await {
+                Ok((template_rx, tasks)) => {
+                    return Ok(TpConnection {
+                        index,
+                        template_rx,
+                        tasks,
+                    })
+                }
+                Err(e) => {
+                    warn!("Could not connect to template provider {}: {}", address, e);
+                    last_error = e;
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Resolves once a TCP connection to `address` succeeds, probing every `interval`.
+    async fn wait_until_reachable(address: SocketAddr, interval: Duration) {
+        loop {
+            tokio::time::sleep(interval).await;
+            if let Ok(Ok(_)) = tokio::time::timeout(interval, TcpStream::connect(address)).await {
+                return;
+            }
+        }
+    }
+
+    async fn connect_to(
+        connector: &TpConnector,
+        address: SocketAddr,
+        status_tx: status::Sender,
+    ) -> PoolResult<(Arc<Mutex<Self>>, Vec<task::AbortHandle>)> {
         let stream = TcpStream::connect(address).await?;
         info!("Connected to template distribution server at {}", address);
 
-        let initiator = match expected_tp_authority_public_key {
+        let initiator = match connector.expected_tp_authority_public_key {
             Some(expected_tp_authority_public_key) => {
                 Initiator::from_raw_k(expected_tp_authority_public_key.into_bytes())
             }
             None => Initiator::without_pk(),
         }?;
-        let (mut receiver, mut sender, _, _) =
+        let (mut receiver, mut sender, reader_task, writer_task) =
             Connection::new(stream, HandshakeRole::Initiator(initiator))
                 .await
-                .unwrap();
+                .map_err(|e| {
+                    PoolError::Custom(format!("Noise handshake with {} failed: {:?}", address, e))
+                })?;
 
         SetupConnectionHandler::setup(&mut receiver, &mut sender, address).await?;
 
         let self_ = Arc::new(Mutex::new(Self {
             receiver,
             sender,
-            new_template_sender: templ_sender,
-            new_prev_hash_sender: prev_h_sender,
-            message_received_signal,
+            new_template_sender: connector.templ_sender.clone(),
+            new_prev_hash_sender: connector.prev_h_sender.clone(),
+            message_received_signal: connector.message_received_signal.clone(),
             status_tx,
         }));
-        let cloned = self_.clone();
 
         let c_additional_size = CoinbaseOutputDataSize {
-            coinbase_output_max_additional_size: coinbase_out_len,
+            coinbase_output_max_additional_size: connector.coinbase_out_len,
         };
         let frame = PoolMessages::TemplateDistribution(
             TemplateDistribution::CoinbaseOutputDataSize(c_additional_size),
@@ -81,13 +268,21 @@ impl TemplateRx {
 
         Self::send(self_.clone(), frame).await?;
 
-        task::spawn(async { Self::start(cloned).await });
-        task::spawn(async { Self::on_new_solution(self_, solution_receiver).await });
+        let solution_task = task::spawn(Self::on_new_solution(
+            self_.clone(),
+            connector.solution_receiver.clone(),
+        ));
 
-        Ok(())
+        Ok((
+            self_,
+            vec![reader_task, writer_task, solution_task.abort_handle()],
+        ))
     }
 
-    pub async fn start(self_: Arc<Mutex<Self>>) {
+    /// Forwards the messages of the template provider to the mining pool until the connection
+    /// drops or `failback` is set. `failback` is only checked between messages, once the pool
+    /// acknowledged the last one.
+    pub async fn start(self_: Arc<Mutex<Self>>, mut failback: watch::Receiver<bool>) {
         let (recv_msg_signal, receiver, new_template_sender, new_prev_hash_sender, status_tx) =
             self_
                 .safe_lock(|s| {
@@ -100,8 +295,13 @@ impl TemplateRx {
                     )
                 })
                 .unwrap();
+        let mut metrics = TemplateMetrics::default();
         loop {
-            let message_from_tp = handle_result!(status_tx, receiver.recv().await);
+            let message_from_tp = select! {
+                message = receiver.recv() => handle_result!(status_tx, message),
+                _ = failback.changed() => break,
+            };
+            let received_at = Instant::now();
             let mut message_from_tp: StdFrame = handle_result!(
                 status_tx,
                 message_from_tp
@@ -113,6 +313,7 @@ impl TemplateRx {
                 .ok_or_else(|| PoolError::Custom(String::from("No header set")));
             let message_type = handle_result!(status_tx, message_type_res).msg_type();
             let payload = message_from_tp.payload();
+            let payload_len = payload.len();
             let msg = handle_result!(
                 status_tx,
                 ParseServerTemplateDistributionMessages::handle_message_template_distribution(
@@ -125,17 +326,35 @@ impl TemplateRx {
                 roles_logic_sv2::handlers::SendTo_::RelayNewMessageToRemote(_, m) => match m {
                     TemplateDistribution::CoinbaseOutputDataSize(_) => todo!(),
                     TemplateDistribution::NewTemplate(m) => {
+                        let template_id = m.template_id;
+                        let future = m.future_template;
                         let res = new_template_sender.send(m).await;
                         handle_result!(status_tx, res);
+                        // the mining pool signals once the jobs were sent to every downstream
                         handle_result!(status_tx, recv_msg_signal.recv().await);
+                        let elapsed = received_at.elapsed();
+                        metrics.on_new_template(payload_len, future, elapsed);
+                        debug!(
+                            "Template {} ({} bytes) broadcast to downstreams in {:?}",
+                            template_id, payload_len, elapsed
+                        );
                     }
                     TemplateDistribution::RequestTransactionData(_) => todo!(),
                     TemplateDistribution::RequestTransactionDataError(_) => todo!(),
                     TemplateDistribution::RequestTransactionDataSuccess(_) => todo!(),
                     TemplateDistribution::SetNewPrevHash(m) => {
+                        let template_id = m.template_id;
                         let res = new_prev_hash_sender.send(m).await;
                         handle_result!(status_tx, res);
                         handle_result!(status_tx, recv_msg_signal.recv().await);
+                        let elapsed = received_at.elapsed();
+                        metrics.on_new_prev_hash(elapsed);
+                        info!(
+                            "New prev hash for template {} broadcast to downstreams in {:?}",
+                            template_id, elapsed
+                        );
+                        // summarize once per block
+                        info!("Template provider metrics: {}", metrics);
                     }
                     TemplateDistribution::SubmitSolution(_) => todo!(),
                 },
@@ -176,3 +395,141 @@ impl TemplateRx {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use binary_sv2::Seq0255;
+    use codec_sv2::Responder;
+    use key_utils::Secp256k1SecretKey;
+    use roles_logic_sv2::common_messages_sv2::SetupConnectionSuccess;
+    use tokio::net::TcpListener;
+
+    /// Accepts a connection like a template provider would, up to the pool's
+    /// `CoinbaseOutputDataSize`, returning the connection and its noise tasks.
+    async fn accept_pool(listener: &TcpListener) -> (Sender<EitherFrame>, Vec<task::AbortHandle>) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let pub_key = "9auqWEzQDVyd2oe1JVGFLMLHZtCo2FFqZwtKA5gd9xbuEu7PH72"
+            .parse::<Secp256k1PublicKey>()
+            .unwrap()
+            .into_bytes();
+        let prv_key = "mkDLTBBRxdBv998612qipDYoTK3YUrqLe8uWw7gu3iXbSrn2n"
+            .parse::<Secp256k1SecretKey>()
+            .unwrap()
+            .into_bytes();
+        let responder =
+            Responder::from_authority_kp(&pub_key, &prv_key, Duration::from_secs(10000)).unwrap();
+        let (receiver, sender, reader_task, writer_task) =
+            Connection::new(stream, HandshakeRole::Responder(responder))
+                .await
+                .unwrap();
+        // SetupConnection
+        receiver.recv().await.unwrap();
+        let success = SetupConnectionSuccess {
+            used_version: 2,
+            flags: 0,
+        };
+        let frame: StdFrame = PoolMessages::Common(success.into()).try_into().unwrap();
+        sender.send(frame.into()).await.unwrap();
+        // CoinbaseOutputDataSize
+        receiver.recv().await.unwrap();
+        (sender, vec![reader_task, writer_task])
+    }
+
+    fn new_template(template_id: u64) -> StdFrame {
+        let template = NewTemplate {
+            template_id,
+            future_template: true,
+            version: 536870912,
+            coinbase_tx_version: 2,
+            coinbase_prefix: vec![3, 76, 163, 38, 0].try_into().unwrap(),
+            coinbase_tx_input_sequence: u32::MAX,
+            coinbase_tx_value_remaining: 625000000,
+            coinbase_tx_outputs_count: 0,
+            coinbase_tx_outputs: vec![].try_into().unwrap(),
+            coinbase_tx_locktime: 0,
+            merkle_path: Seq0255::new(vec![]).unwrap(),
+        };
+        PoolMessages::TemplateDistribution(TemplateDistribution::NewTemplate(template))
+            .try_into()
+            .unwrap()
+    }
+
+    fn connector() -> (TpConnector, Receiver<NewTemplate<'static>>, Sender<()>) {
+        let (templ_sender, templ_receiver) = unbounded();
+        let (prev_h_sender, _) = unbounded();
+        let (_, solution_receiver) = unbounded();
+        let (signal_sender, message_received_signal) = async_channel::bounded(10);
+        let connector = TpConnector {
+            templ_sender,
+            prev_h_sender,
+            solution_receiver,
+            message_received_signal,
+            coinbase_out_len: 0,
+            expected_tp_authority_public_key: None,
+        };
+        (connector, templ_receiver, signal_sender)
+    }
+
+    #[tokio::test]
+    async fn connects_to_the_next_address_when_one_is_unreachable() {
+        let unreachable = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let unreachable_address = unreachable.local_addr().unwrap();
+        drop(unreachable);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let tp = task::spawn(async move { accept_pool(&listener).await });
+
+        let (connector, _, _) = connector();
+        let (status_tx, _status_rx) = unbounded();
+        let connection = TemplateRx::connect_any(
+            &connector,
+            &[unreachable_address, address],
+            status::Sender::Upstream(status_tx),
+        )
+        .await
+        .unwrap();
+        assert_eq!(connection.index, 1);
+        tp.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn reconnects_when_the_template_provider_drops_the_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (connector, templ_receiver, signal_sender) = connector();
+        let tp = task::spawn(async move {
+            // the first connection is closed as soon as it is set up
+            let (_, tasks) = accept_pool(&listener).await;
+            for task in tasks {
+                task.abort();
+            }
+            let (sender, tasks) = accept_pool(&listener).await;
+            sender.send(new_template(1).into()).await.unwrap();
+            (sender, tasks)
+        });
+
+        TemplateRx::connect(
+            vec![address],
+            Duration::from_secs(60),
+            Duration::from_secs(1),
+            connector.templ_sender,
+            connector.prev_h_sender,
+            connector.solution_receiver,
+            connector.message_received_signal,
+            connector.coinbase_out_len,
+            None,
+        )
+        .await
+        .unwrap();
+
+        // templates keep flowing from the new connection
+        let template = tokio::time::timeout(Duration::from_secs(10), templ_receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(template.template_id, 1);
+        signal_sender.send(()).await.unwrap();
+        tp.await.unwrap();
+    }
+}
@@ -50,6 +50,55 @@ impl Downstream {
         Ok(())
     }
 
+    /// Seeds the miner hashrate estimate from a SV1 `mining.suggest_difficulty` hint. The
+    /// suggestion is clamped to the upstream channel difficulty, since a harder downstream target
+    /// would discard shares that the upstream still accepts. Suggestions received after the first
+    /// job was sent are ignored and left to the vardiff loop.
+    #[allow(clippy::result_large_err)]
+    pub(super) fn apply_suggested_difficulty(
+        &mut self,
+        suggested_difficulty: f64,
+    ) -> ProxyResult<'static, ()> {
+        if self.first_job_received {
+            tracing::warn!(
+                "Suggested difficulty {} overridden for {}: vardiff already started",
+                suggested_difficulty, self.connection_id
+            );
+            return Ok(());
+        }
+        if !suggested_difficulty.is_finite() || suggested_difficulty <= 0.0 {
+            tracing::warn!(
+                "Suggested difficulty {} overridden for {}: invalid value",
+                suggested_difficulty, self.connection_id
+            );
+            return Ok(());
+        }
+        let upstream_target = self
+            .upstream_target
+            .safe_lock(|t| t.clone())
+            .map_err(|_e| Error::PoisonLock)?;
+        let upstream_difficulty = Self::difficulty_from_target(upstream_target)?;
+        let difficulty = if upstream_difficulty > 0.0 && suggested_difficulty > upstream_difficulty
+        {
+            tracing::warn!(
+                "Suggested difficulty {} overridden for {}: clamped to upstream difficulty {}",
+                suggested_difficulty, self.connection_id, upstream_difficulty
+            );
+            upstream_difficulty
+        } else {
+            suggested_difficulty
+        };
+        // a difficulty 1 share takes 2^32 hashes on average
+        let hashrate =
+            difficulty * 2_f64.powi(32) * self.difficulty_mgmt.shares_per_minute as f64 / 60.0;
+        tracing::debug!(
+            "Initial hashrate for {} set to {} from suggested difficulty",
+            self.connection_id, hashrate
+        );
+        self.difficulty_mgmt.min_individual_miner_hashrate = hashrate as f32;
+        Ok(())
+    }
+
     /// Called before a miner disconnects so we can remove the miner's hashrate from the aggregated
     /// channel hashrate
     #[allow(clippy::result_large_err)]
@@ -443,6 +492,7 @@ mod test {
             0,
             downstream_conf.clone(),
             Arc::new(Mutex::new(upstream_config)),
+            Arc::new(Mutex::new(vec![0; 32])),
             "0".to_string(),
         );
         downstream.difficulty_mgmt.min_individual_miner_hashrate = start_hashrate as f32;
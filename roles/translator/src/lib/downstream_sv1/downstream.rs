@@ -3,6 +3,7 @@ use crate::{
     error::ProxyResult,
     proxy_config::{DownstreamDifficultyConfig, UpstreamDifficultyConfig},
     status,
+    task_supervisor::{RestartPolicy, TaskSupervisor},
 };
 use async_channel::{bounded, Receiver, Sender};
 use async_std::{
@@ -13,7 +14,7 @@ use async_std::{
 };
 use error_handling::handle_result;
 use futures::FutureExt;
-use tokio::{sync::broadcast, task::AbortHandle};
+use tokio::sync::broadcast;
 
 use super::{kill, DownstreamMessages, SubmitShareWithChannelId, SUBSCRIBE_TIMEOUT_SECS};
 
@@ -62,6 +63,8 @@ pub struct Downstream {
     extranonce2_len: usize,
     pub(super) difficulty_mgmt: DownstreamDifficultyConfig,
     pub(super) upstream_difficulty_config: Arc<Mutex<UpstreamDifficultyConfig>>,
+    /// Target of the upstream channel, used to clamp difficulties suggested by the miner.
+    pub(super) upstream_target: Arc<Mutex<Vec<u8>>>,
     last_job_id: String, // we usually receive a String on SV1 messages, no need to cast to u32
 }
 
@@ -79,6 +82,7 @@ impl Downstream {
         extranonce2_len: usize,
         difficulty_mgmt: DownstreamDifficultyConfig,
         upstream_difficulty_config: Arc<Mutex<UpstreamDifficultyConfig>>,
+        upstream_target: Arc<Mutex<Vec<u8>>>,
         last_job_id: String,
     ) -> Self {
         Downstream {
@@ -93,6 +97,7 @@ impl Downstream {
             extranonce2_len,
             difficulty_mgmt,
             upstream_difficulty_config,
+            upstream_target,
             last_job_id,
         }
     }
@@ -110,7 +115,8 @@ impl Downstream {
         host: String,
         difficulty_config: DownstreamDifficultyConfig,
         upstream_difficulty_config: Arc<Mutex<UpstreamDifficultyConfig>>,
-        task_collector: Arc<Mutex<Vec<(AbortHandle, String)>>>,
+        upstream_target: Arc<Mutex<Vec<u8>>>,
+        task_collector: Arc<Mutex<TaskSupervisor>>,
     ) {
         let stream = std::sync::Arc::new(stream);
 
@@ -135,6 +141,7 @@ impl Downstream {
             extranonce2_len,
             difficulty_mgmt: difficulty_config,
             upstream_difficulty_config,
+            upstream_target,
             last_job_id: "".to_string(),
         }));
         let self_ = downstream.clone();
@@ -211,10 +218,7 @@ impl Downstream {
             warn!("Downstream: Shutting down sv1 downstream reader");
         });
         let _ = task_collector_mining_device.safe_lock(|a| {
-            a.push((
-                socket_reader_task.abort_handle(),
-                "socket_reader_task".to_string(),
-            ))
+            a.register("socket_reader_task", socket_reader_task.abort_handle())
         });
 
         let rx_shutdown_clone = rx_shutdown.clone();
@@ -255,10 +259,7 @@ impl Downstream {
             );
         });
         let _ = task_collector_new_sv1_message_no_transl.safe_lock(|a| {
-            a.push((
-                socket_writer_task.abort_handle(),
-                "socket_writer_task".to_string(),
-            ))
+            a.register("socket_writer_task", socket_writer_task.abort_handle())
         });
 
         let tx_status_notify = tx_status;
@@ -353,7 +354,7 @@ impl Downstream {
         });
 
         let _ = task_collector_notify_task
-            .safe_lock(|a| a.push((notify_task.abort_handle(), "notify_task".to_string())));
+            .safe_lock(|a| a.register("notify_task", notify_task.abort_handle()));
     }
 
     /// Accept connections from one or more SV1 Downstream roles (SV1 Mining Devices) and create a
@@ -367,7 +368,7 @@ impl Downstream {
         bridge: Arc<Mutex<crate::proxy::Bridge>>,
         downstream_difficulty_config: DownstreamDifficultyConfig,
         upstream_difficulty_config: Arc<Mutex<UpstreamDifficultyConfig>>,
-        task_collector: Arc<Mutex<Vec<(AbortHandle, String)>>>,
+        task_collector: Arc<Mutex<TaskSupervisor>>,
     ) {
         let task_collector_downstream = task_collector.clone();
 
@@ -398,6 +399,7 @@ impl Downstream {
                             host,
                             downstream_difficulty_config.clone(),
                             upstream_difficulty_config.clone(),
+                            opened.target,
                             task_collector_downstream.clone(),
                         )
                         .await;
@@ -409,10 +411,12 @@ impl Downstream {
             }
         });
         let _ = task_collector.safe_lock(|a| {
-            a.push((
+            a.supervise(
+                "accept_connections",
                 accept_connections.abort_handle(),
-                "accept_connections".to_string(),
-            ))
+                RestartPolicy::RestartAll,
+                None,
+            )
         });
     }
 
@@ -566,6 +570,15 @@ impl IsServer<'static> for Downstream {
     /// Indicates to the server that the client supports the mining.set_extranonce method.
     fn handle_extranonce_subscribe(&self) {}
 
+    /// Uses the difficulty suggested by the miner (common with NiceHash-style clients) as the
+    /// initial hint for the vardiff engine.
+    fn handle_suggest_difficulty(&mut self, request: &client_to_server::SuggestDifficulty) {
+        info!("Down: Miner suggested difficulty {}", request.value);
+        if let Err(e) = self.apply_suggested_difficulty(request.value) {
+            warn!("Down: Failed to apply suggested difficulty: {:?}", e);
+        }
+    }
+
     /// Checks if a Downstream role is authorized.
     fn is_authorized(&self, name: &str) -> bool {
         self.authorized_names.contains(&name.to_string())
@@ -8,27 +8,30 @@ use std::{
     net::{IpAddr, SocketAddr},
     str::FromStr,
     sync::Arc,
+    time::{Duration, Instant},
 };
 
-use tokio::{
-    sync::broadcast,
-    task::{self, AbortHandle},
-};
+use tokio::{sync::broadcast, task};
 use tracing::{debug, error, info, warn};
 pub use v1::server_to_client;
 
+use proxy::WorkerLedger;
 use proxy_config::ProxyConfig;
 
-use crate::status::State;
+use crate::{status::State, task_supervisor::TaskSupervisor};
 
 pub mod downstream_sv1;
 pub mod error;
 pub mod proxy;
 pub mod proxy_config;
 pub mod status;
+pub mod task_supervisor;
 pub mod upstream_sv2;
 pub mod utils;
 
+/// How often the supervised tasks are checked for ones that ended or stalled.
+const LIVENESS_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
 // TODO consolidate, these consts are defined all over the place
 pub const HASH_CURRENCY_UNIT: &str = "HASH";
 
@@ -36,7 +39,8 @@ pub const HASH_CURRENCY_UNIT: &str = "HASH";
 pub struct TranslatorSv2 {
     config: ProxyConfig,
     reconnect_wait_time: u64,
-    wallet: Arc<Wallet>,
+    /// Ehash credited to each SV1 worker and the wallets holding it
+    worker_ledger: Arc<Mutex<WorkerLedger>>,
 }
 
 fn create_wallet() -> Arc<Wallet> {
@@ -59,11 +63,27 @@ impl TranslatorSv2 {
         Self {
             config,
             reconnect_wait_time: wait_time,
-            wallet: create_wallet(),
+            worker_ledger: Arc::new(Mutex::new(WorkerLedger::new())),
         }
     }
 
     pub async fn start(self) {
+        if let Some(path) = &self.config.worker_ledger_path {
+            match WorkerLedger::open(path) {
+                Ok(ledger) => {
+                    info!(
+                        "Restored the balances of {} workers from {}",
+                        ledger.balances().len(),
+                        path
+                    );
+                    let _ = self.worker_ledger.safe_lock(|l| *l = ledger);
+                }
+                Err(e) => {
+                    error!("Failed to open worker ledger {}: {}", path, e);
+                    return;
+                }
+            }
+        }
         let (tx_status, rx_status) = unbounded();
 
         let target = Arc::new(Mutex::new(vec![0; 32]));
@@ -74,8 +94,8 @@ impl TranslatorSv2 {
             broadcast::Receiver<server_to_client::Notify>,
         ) = broadcast::channel(10);
 
-        let task_collector: Arc<Mutex<Vec<(AbortHandle, String)>>> =
-            Arc::new(Mutex::new(Vec::new()));
+        let task_collector: Arc<Mutex<TaskSupervisor>> =
+            Arc::new(Mutex::new(TaskSupervisor::new()));
 
         self.internal_start(
             tx_sv1_notify.clone(),
@@ -89,11 +109,25 @@ impl TranslatorSv2 {
         let task_collector_ = task_collector.clone();
 
         debug!("Starting up status listener");
-        let wait_time = self.reconnect_wait_time;
+        let mut liveness_check = tokio::time::interval(LIVENESS_CHECK_INTERVAL);
         // Check all tasks if is_finished() is true, if so exit
         loop {
             let task_status = tokio::select! {
                 task_status = rx_status.recv().fuse() => task_status,
+                _ = liveness_check.tick().fuse() => {
+                    let failed = task_collector_.safe_lock(|t| t.needs_restart(Instant::now()));
+                    if let Ok(Some(task)) = failed {
+                        error!("Supervised task {} ended or stalled, restarting", task);
+                        self.restart(
+                            tx_sv1_notify.clone(),
+                            target.clone(),
+                            tx_status.clone(),
+                            task_collector_.clone(),
+                        )
+                        .await;
+                    }
+                    continue;
+                }
                 interrupt_signal = tokio::signal::ctrl_c().fuse() => {
                     match interrupt_signal {
                         Ok(()) => {
@@ -113,30 +147,23 @@ impl TranslatorSv2 {
                 // Should only be sent by the downstream listener
                 State::DownstreamShutdown(err) => {
                     error!("SHUTDOWN from: {}", err);
+                    record_error(&task_collector_, "downstream", err.to_string());
                     break;
                 }
                 State::BridgeShutdown(err) => {
                     error!("SHUTDOWN from: {}", err);
+                    record_error(&task_collector_, "bridge", err.to_string());
                     break;
                 }
                 State::UpstreamShutdown(err) => {
                     error!("SHUTDOWN from: {}", err);
+                    record_error(&task_collector_, "upstream", err.to_string());
                     break;
                 }
                 State::UpstreamTryReconnect(err) => {
                     error!("SHUTDOWN from: {}", err);
-
-                    // wait a random amount of time between 0 and 3000ms
-                    // if all the downstreams try to reconnect at the same time, the upstream may
-                    // fail
-                    tokio::time::sleep(std::time::Duration::from_millis(wait_time)).await;
-
-                    // kill al the tasks
-                    let task_collector_aborting = task_collector_.clone();
-                    kill_tasks(task_collector_aborting.clone());
-
-                    warn!("Trying reconnecting to upstream");
-                    self.internal_start(
+                    record_error(&task_collector_, "upstream", err.to_string());
+                    self.restart(
                         tx_sv1_notify.clone(),
                         target.clone(),
                         tx_status.clone(),
@@ -149,6 +176,28 @@ impl TranslatorSv2 {
                 }
             }
         }
+        log_worker_balances(&self.worker_ledger);
+    }
+
+    /// Kills every supervised task and starts over by reconnecting to the upstream.
+    async fn restart(
+        &self,
+        tx_sv1_notify: broadcast::Sender<server_to_client::Notify<'static>>,
+        target: Arc<Mutex<Vec<u8>>>,
+        tx_status: async_channel::Sender<Status<'static>>,
+        task_collector: Arc<Mutex<TaskSupervisor>>,
+    ) {
+        // wait a random amount of time between 0 and 3000ms
+        // if all the downstreams try to reconnect at the same time, the upstream may
+        // fail
+        tokio::time::sleep(Duration::from_millis(self.reconnect_wait_time)).await;
+
+        // kill al the tasks
+        kill_tasks(task_collector.clone());
+
+        warn!("Trying reconnecting to upstream");
+        self.internal_start(tx_sv1_notify, target, tx_status, task_collector)
+            .await;
     }
 
     async fn internal_start(
@@ -156,7 +205,7 @@ impl TranslatorSv2 {
         tx_sv1_notify: broadcast::Sender<server_to_client::Notify<'static>>,
         target: Arc<Mutex<Vec<u8>>>,
         tx_status: async_channel::Sender<Status<'static>>,
-        task_collector: Arc<Mutex<Vec<(AbortHandle, String)>>>,
+        task_collector: Arc<Mutex<TaskSupervisor>>,
     ) {
         let proxy_config = self.config.clone();
         // Sender/Receiver to send a SV2 `SubmitSharesExtended` from the `Bridge` to the `Upstream`
@@ -205,7 +254,7 @@ impl TranslatorSv2 {
             target.clone(),
             diff_config.clone(),
             task_collector_upstream,
-            self.wallet.clone(),
+            self.worker_ledger.clone(),
         )
         .await
         {
@@ -216,7 +265,7 @@ impl TranslatorSv2 {
             }
         };
         let task_collector_init_task = task_collector.clone();
-        let wallet = self.wallet.clone();
+        let worker_ledger = self.worker_ledger.clone();
         // Spawn a task to do all of this init work so that the main thread
         // can listen for signals and failures on the status channel. This
         // allows for the tproxy to fail gracefully if any of these init tasks
@@ -274,7 +323,7 @@ impl TranslatorSv2 {
                 target,
                 up_id,
                 task_collector_bridge,
-                wallet,
+                worker_ledger,
             );
             proxy::Bridge::start(b.clone());
 
@@ -298,15 +347,41 @@ impl TranslatorSv2 {
             );
         }); // End of init task
         let _ =
-            task_collector.safe_lock(|t| t.push((task.abort_handle(), "init task".to_string())));
+            task_collector.safe_lock(|t| t.register("init task", task.abort_handle()));
     }
 }
 
-fn kill_tasks(task_collector: Arc<Mutex<Vec<(AbortHandle, String)>>>) {
+fn kill_tasks(task_collector: Arc<Mutex<TaskSupervisor>>) {
+    let _ = task_collector.safe_lock(|t| t.abort_all());
+}
+
+fn record_error(task_collector: &Arc<Mutex<TaskSupervisor>>, source: &str, error: String) {
     let _ = task_collector.safe_lock(|t| {
-        while let Some(handle) = t.pop() {
-            handle.0.abort();
-            warn!("Killed task: {:?}", handle.1);
+        t.record_error(source, error);
+        log_tasks(t);
+    });
+}
+
+fn log_worker_balances(worker_ledger: &Arc<Mutex<WorkerLedger>>) {
+    let _ = worker_ledger.safe_lock(|l| {
+        for (worker, balance) in l.balances() {
+            info!(
+                "Worker {}: {} ehash for {} shares of total work {}",
+                worker, balance.ehash, balance.shares, balance.work
+            );
         }
     });
 }
+
+fn log_tasks(supervisor: &TaskSupervisor) {
+    info!("{} supervised tasks running", supervisor.running());
+    for task in supervisor.snapshot() {
+        debug!(
+            "Task {:?}: {:?} for {:?}, restart {:?}, last heartbeat {:?} ago, last error {:?}",
+            task.name, task.state, task.age, task.policy, task.since_heartbeat, task.last_error
+        );
+    }
+    for (source, error) in supervisor.errors() {
+        info!("Last error from {}: {}", source, error);
+    }
+}
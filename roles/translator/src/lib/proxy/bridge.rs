@@ -1,5 +1,4 @@
 use async_channel::{Receiver, Sender};
-use cdk::wallet::Wallet;
 use roles_logic_sv2::{
     channel_logic::channel_factory::{ExtendedChannelKind, ProxyExtendedChannelFactory, Share},
     mining_sv2::{
@@ -10,7 +9,7 @@ use roles_logic_sv2::{
 };
 use stratum_common::bitcoin::hashes::hex::ToHex;
 use std::sync::Arc;
-use tokio::{sync::broadcast, task::AbortHandle};
+use tokio::sync::broadcast;
 use v1::{client_to_server::Submit, server_to_client, utils::HexU32Be};
 
 use super::super::{
@@ -20,7 +19,9 @@ use super::super::{
         ProxyResult,
     },
     status,
+    task_supervisor::{RestartPolicy, TaskSupervisor},
 };
+use super::WorkerLedger;
 use error_handling::handle_result;
 use roles_logic_sv2::{channel_logic::channel_factory::OnNewShare, Error as RolesLogicError};
 use tracing::{debug, error, info, warn};
@@ -66,8 +67,9 @@ pub struct Bridge {
     last_p_hash: Option<SetNewPrevHash<'static>>,
     target: Arc<Mutex<Vec<u8>>>,
     last_job_id: u32,
-    task_collector: Arc<Mutex<Vec<(AbortHandle, String)>>>,
-    wallet: Arc<Wallet>,
+    task_collector: Arc<Mutex<TaskSupervisor>>,
+    /// Records which SV1 worker found each share sent upstream and holds the wallet of each
+    worker_ledger: Arc<Mutex<WorkerLedger>>,
 }
 
 impl Bridge {
@@ -83,8 +85,8 @@ impl Bridge {
         extranonces: ExtendedExtranonce,
         target: Arc<Mutex<Vec<u8>>>,
         up_id: u32,
-        task_collector: Arc<Mutex<Vec<(AbortHandle, String)>>>,
-        wallet: Arc<Wallet>,
+        task_collector: Arc<Mutex<TaskSupervisor>>,
+        worker_ledger: Arc<Mutex<WorkerLedger>>,
     ) -> Arc<Mutex<Self>> {
         let ids = Arc::new(Mutex::new(GroupId::new()));
         let share_per_min = 1.0;
@@ -115,7 +117,7 @@ impl Bridge {
             target,
             last_job_id: 0,
             task_collector,
-            wallet,
+            worker_ledger,
         }))
     }
 
@@ -197,10 +199,12 @@ impl Bridge {
             }
         });
         let _ = task_collector_handle_downstream.safe_lock(|a| {
-            a.push((
+            a.supervise(
+                "handle_downstream_message",
                 handle_downstream.abort_handle(),
-                "handle_downstream_message".to_string(),
-            ))
+                RestartPolicy::RestartAll,
+                None,
+            )
         });
     }
     /// receives a `SetDownstreamTarget` and updates the downstream target for the channel
@@ -223,12 +227,13 @@ impl Bridge {
         self_: Arc<Mutex<Self>>,
         share: SubmitShareWithChannelId,
     ) -> ProxyResult<'static, ()> {
-        let (tx_sv2_submit_shares_ext, target_mutex, tx_status) = self_
+        let (tx_sv2_submit_shares_ext, target_mutex, tx_status, worker_ledger) = self_
             .safe_lock(|s| {
                 (
                     s.tx_sv2_submit_shares_ext.clone(),
                     s.target.clone(),
                     s.tx_status.clone(),
+                    s.worker_ledger.clone(),
                 )
             })
             .map_err(|_| PoisonLock)?;
@@ -241,6 +246,7 @@ impl Bridge {
             .safe_lock(|s| s.channel_factory.set_target(&mut upstream_target))
             .map_err(|_| PoisonLock)?;
 
+        let worker = share.share.user_name.clone();
         let sv2_submit = self_
             .safe_lock(|s| {
                 s.translate_submit(share.channel_id, share.share, share.version_rolling_mask)
@@ -262,7 +268,7 @@ impl Bridge {
                 match share {
                     Share::Extended(mut share) => {
                         let premint_secrets = self_.safe_lock(|bridge| {
-                            match bridge.create_blinded_secrets(&share) {
+                            match bridge.create_blinded_secrets(&share, &worker) {
                                 Ok(secrets) => secrets,
                                 Err(e) => {
                                     println!("Failed to create blinded secret: {:?}", e);
@@ -282,7 +288,14 @@ impl Bridge {
                         let sv2_blinded_message_set_wire= Sv2BlindedMessageSetWire::from(blinded_message_set);
 
                         share.blinded_messages = sv2_blinded_message_set_wire;
-    
+
+                        let mut hash = [0u8; 32];
+                        hash.copy_from_slice(share.hash.inner_as_ref());
+                        let work = Self::calculate_work(hash);
+                        worker_ledger
+                            .safe_lock(|l| l.on_share_submitted(hash, worker, work))
+                            .map_err(|_| PoisonLock)?;
+
                         tx_sv2_submit_shares_ext.send(share).await?;
                     }
                     // We are in an extended channel; shares are extended
@@ -308,18 +321,31 @@ impl Bridge {
         Ok(())
     }
 
+    /// Generates the premint secrets of a share in the wallet of the worker that found it, so
+    /// the proofs minted for the share end up in that wallet.
     fn create_blinded_secrets(
         &mut self,
         share: &SubmitSharesExtended,
+        worker: &str,
     ) -> Result<cdk::nuts::PreMintSecrets, Error<'static>> {
         // TODO is it better to recalculate this value from the share or to pass it over the wire?
         let share_hash = share.hash.to_vec().to_hex();
         let work = Self::calculate_work(share.hash.to_vec().try_into()?);
+        let (wallet, keys) = self
+            .worker_ledger
+            .safe_lock(|l| l.wallet(worker))
+            .map_err(|_| PoisonLock)?;
 
         tokio::task::block_in_place(|| {
-            let wallet_clone = self.wallet.clone();
+            if let Some(keys) = keys {
+                let added = tokio::runtime::Handle::current()
+                    .block_on(wallet.add_keyset(keys, true, 0));
+                if let Err(e) = added {
+                    warn!("Failed to add keyset to the wallet of {}: {:?}", worker, e);
+                }
+            }
             tokio::runtime::Handle::current()
-                .block_on(wallet_clone.gen_ehash_premint_secrets(
+                .block_on(wallet.gen_ehash_premint_secrets(
                     work,
                     &share_hash,
                     "http://localhost:8000"
@@ -478,10 +504,12 @@ impl Bridge {
             }
         });
         let _ = task_collector_handle_new_prev_hash.safe_lock(|a| {
-            a.push((
+            a.supervise(
+                "handle_new_prev_hash",
                 handle_new_prev_hash.abort_handle(),
-                "handle_new_prev_hash".to_string(),
-            ))
+                RestartPolicy::RestartAll,
+                None,
+            )
         });
     }
 
@@ -584,10 +612,12 @@ impl Bridge {
             }
         });
         let _ = task_collector_new_extended_mining_job.safe_lock(|a| {
-            a.push((
+            a.supervise(
+                "handle_new_extended_mining_job",
                 handle_new_extended_mining_job.abort_handle(),
-                "handle_new_extended_mining_job".to_string(),
-            ))
+                RestartPolicy::RestartAll,
+                None,
+            )
         });
     }
 }
@@ -607,8 +637,6 @@ mod test {
     use stratum_common::bitcoin::util::psbt::serialize::Serialize;
 
     pub mod test_utils {
-        use crate::create_wallet;
-
         use super::*;
 
         #[allow(dead_code)]
@@ -641,7 +669,7 @@ mod test {
                 rx_sv1_notify,
             };
 
-            let task_collector = Arc::new(Mutex::new(vec![]));
+            let task_collector = Arc::new(Mutex::new(TaskSupervisor::new()));
             let b = Bridge::new(
                 rx_sv1_submit,
                 tx_sv2_submit_shares_ext,
@@ -654,7 +682,7 @@ mod test {
                 1,
                 task_collector,
                 // TODO test ecash stuff
-                create_wallet(),
+                Arc::new(Mutex::new(WorkerLedger::new())),
             );
             (b, interface)
         }
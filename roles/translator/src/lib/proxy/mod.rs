@@ -1,3 +1,5 @@
 pub mod bridge;
 pub mod next_mining_notify;
+pub mod worker_ledger;
 pub use bridge::Bridge;
+pub use worker_ledger::WorkerLedger;
@@ -0,0 +1,212 @@
+use cdk::{nuts::Keys, wallet::Wallet};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use stratum_common::bitcoin::hashes::hex::ToHex;
+
+use crate::create_wallet;
+
+/// Maximum number of shares sent upstream that wait for their ehash, the oldest are forgotten
+/// when the pool never acknowledges them.
+const MAX_PENDING_SHARES: usize = 1024;
+
+/// Work and ehash credited to a single SV1 worker.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WorkerBalance {
+    /// Shares acknowledged by the pool
+    pub shares: u64,
+    /// Work of the acknowledged shares, as leading zero bits of their hash
+    pub work: u64,
+    /// Ehash minted for the acknowledged shares
+    pub ehash: u64,
+}
+
+/// Splits the ehash minted by the proxy between the SV1 workers whose shares earned it. Every
+/// share is submitted by a single worker, so its premint secrets are generated in the wallet of
+/// that worker and the proofs minted for it land there too, each wallet holding exactly the
+/// proofs its worker can be paid out.
+///
+/// When opened on a file, every credit is appended to it as
+/// `<timestamp> <share hash> <work> <ehash> <worker>` and the balances are rebuilt from it on
+/// startup. The wallets are memory backed, so only the balances survive a restart.
+#[derive(Debug, Default)]
+pub struct WorkerLedger {
+    /// Shares sent upstream and not acknowledged yet, with the worker and work of each
+    pending: VecDeque<([u8; 32], String, u64)>,
+    balances: HashMap<String, WorkerBalance>,
+    /// Wallet holding the proofs minted for the shares of each worker
+    wallets: HashMap<String, Arc<Wallet>>,
+    /// Keys of the mint keyset of the upstream channel, added to every wallet
+    keys: Option<Keys>,
+    file: Option<File>,
+}
+
+impl WorkerLedger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Opens the ledger file at `path`, creating it if needed, and restores the balances of the
+    /// credits it holds.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut ledger = Self::new();
+        if path.as_ref().exists() {
+            for line in BufReader::new(File::open(&path)?).lines() {
+                let line = line?;
+                if line.is_empty() {
+                    continue;
+                }
+                let (worker, work, ehash) = parse_line(&line)?;
+                ledger.credit(worker, work, ehash);
+            }
+        }
+        ledger.file = Some(OpenOptions::new().create(true).append(true).open(path)?);
+        Ok(ledger)
+    }
+
+    /// Returns the wallet of `worker`, creating it on its first share. The mint keys are returned
+    /// along with a new wallet so the caller can add them to it.
+    pub fn wallet(&mut self, worker: &str) -> (Arc<Wallet>, Option<Keys>) {
+        match self.wallets.get(worker) {
+            Some(wallet) => (wallet.clone(), None),
+            None => {
+                let wallet = create_wallet();
+                self.wallets.insert(worker.to_string(), wallet.clone());
+                (wallet, self.keys.clone())
+            }
+        }
+    }
+
+    /// Wallet of the worker that found a share sent upstream, the one its proofs are minted in.
+    pub fn share_wallet(&self, hash: [u8; 32]) -> Option<Arc<Wallet>> {
+        let (_, worker, _) = self.pending.iter().find(|(h, _, _)| *h == hash)?;
+        self.wallets.get(worker).cloned()
+    }
+
+    /// Sets the mint keys of a new upstream channel, returning the wallets they have to be added
+    /// to.
+    pub fn set_keys(&mut self, keys: Keys) -> Vec<Arc<Wallet>> {
+        self.keys = Some(keys);
+        self.wallets.values().cloned().collect()
+    }
+
+    /// Remembers which worker found a share sent upstream.
+    pub fn on_share_submitted(&mut self, hash: [u8; 32], worker: String, work: u64) {
+        if self.pending.len() >= MAX_PENDING_SHARES {
+            self.pending.pop_front();
+        }
+        self.pending.push_back((hash, worker, work));
+    }
+
+    /// Credits the ehash minted for a share to the worker that found it, returning the worker
+    /// and its updated balance, or `None` for a share the ledger does not know about. The
+    /// credit is kept in memory even when appending it to the ledger file fails.
+    pub fn on_ehash_minted(
+        &mut self,
+        hash: [u8; 32],
+        amount: u64,
+    ) -> io::Result<Option<(String, WorkerBalance)>> {
+        let index = match self.pending.iter().position(|(h, _, _)| *h == hash) {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+        let (_, worker, work) = self.pending.remove(index).expect("index is in bounds");
+        let balance = self.credit(worker.clone(), work, amount);
+        if let Some(file) = &mut self.file {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default();
+            writeln!(
+                file,
+                "{} {} {} {} {}",
+                timestamp,
+                hash.to_hex(),
+                work,
+                amount,
+                worker
+            )?;
+            file.flush()?;
+        }
+        Ok(Some((worker, balance)))
+    }
+
+    /// Balances of every worker credited, including the credits restored from the ledger file.
+    pub fn balances(&self) -> &HashMap<String, WorkerBalance> {
+        &self.balances
+    }
+
+    fn credit(&mut self, worker: String, work: u64, amount: u64) -> WorkerBalance {
+        let balance = self.balances.entry(worker).or_default();
+        balance.shares += 1;
+        balance.work += work;
+        balance.ehash += amount;
+        *balance
+    }
+}
+
+/// Reads the worker, work and ehash of a ledger line, the worker name is last so it can hold
+/// spaces.
+fn parse_line(line: &str) -> io::Result<(String, u64, u64)> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("bad entry: {}", line));
+    let mut fields = line.splitn(5, ' ');
+    let _timestamp = fields.next().ok_or_else(invalid)?;
+    let _hash = fields.next().ok_or_else(invalid)?;
+    let work = fields.next().and_then(|f| f.parse().ok()).ok_or_else(invalid)?;
+    let ehash = fields.next().and_then(|f| f.parse().ok()).ok_or_else(invalid)?;
+    let worker = fields.next().ok_or_else(invalid)?;
+    Ok((worker.to_string(), work, ehash))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn splits_minted_ehash_between_the_workers_of_the_shares() {
+        let path = std::env::temp_dir().join(format!("worker-ledger-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut ledger = WorkerLedger::open(&path).unwrap();
+        let (alice_wallet, keys) = ledger.wallet("alice");
+        assert!(keys.is_none());
+        let (bob_wallet, _) = ledger.wallet("bob");
+        assert!(!Arc::ptr_eq(&alice_wallet, &bob_wallet));
+        assert!(Arc::ptr_eq(&ledger.wallet("alice").0, &alice_wallet));
+        ledger.on_share_submitted([1; 32], "alice".to_string(), 40);
+        ledger.on_share_submitted([2; 32], "bob".to_string(), 36);
+        ledger.on_share_submitted([3; 32], "alice".to_string(), 38);
+
+        // the proofs of a share are minted in the wallet of its worker
+        assert!(Arc::ptr_eq(&ledger.share_wallet([3; 32]).unwrap(), &alice_wallet));
+        assert!(Arc::ptr_eq(&ledger.share_wallet([2; 32]).unwrap(), &bob_wallet));
+        assert!(ledger.share_wallet([4; 32]).is_none());
+
+        assert_eq!(ledger.on_ehash_minted([4; 32], 10).unwrap(), None);
+        let (worker, balance) = ledger.on_ehash_minted([3; 32], 38).unwrap().unwrap();
+        assert_eq!(worker, "alice");
+        assert_eq!(balance.ehash, 38);
+        ledger.on_ehash_minted([1; 32], 40).unwrap().unwrap();
+        ledger.on_ehash_minted([2; 32], 36).unwrap().unwrap();
+        // each share is only credited once
+        assert_eq!(ledger.on_ehash_minted([2; 32], 36).unwrap(), None);
+
+        let alice = ledger.balances()["alice"];
+        assert_eq!((alice.shares, alice.work, alice.ehash), (2, 78, 78));
+        let bob = ledger.balances()["bob"];
+        assert_eq!((bob.shares, bob.work, bob.ehash), (1, 36, 36));
+
+        // the balances survive a restart
+        drop(ledger);
+        let ledger = WorkerLedger::open(&path).unwrap();
+        assert_eq!(ledger.balances()["alice"], alice);
+        assert_eq!(ledger.balances()["bob"], bob);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
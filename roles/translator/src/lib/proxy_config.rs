@@ -13,6 +13,10 @@ pub struct ProxyConfig {
     pub min_extranonce2_size: u16,
     pub downstream_difficulty_config: DownstreamDifficultyConfig,
     pub upstream_difficulty_config: UpstreamDifficultyConfig,
+    /// File the ehash credited to each SV1 worker is appended to, balances are only kept in
+    /// memory when unset
+    #[serde(default)]
+    pub worker_ledger_path: Option<String>,
 }
 
 pub struct UpstreamConfig {
@@ -73,6 +77,7 @@ impl ProxyConfig {
             min_extranonce2_size,
             downstream_difficulty_config: downstream.difficulty_config,
             upstream_difficulty_config: upstream.difficulty_config,
+            worker_ledger_path: None,
         }
     }
 }
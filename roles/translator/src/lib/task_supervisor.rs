@@ -0,0 +1,267 @@
+use roles_logic_sv2::utils::Mutex;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::task::AbortHandle;
+use tracing::warn;
+
+/// State of a task registered with the [`TaskSupervisor`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskState {
+    Running,
+    /// Still running but its heartbeat is older than its timeout
+    Stalled,
+    Finished,
+}
+
+/// What the supervisor does when a task finishes or stalls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+    /// The task is allowed to end, like the tasks serving a single SV1 connection
+    Never,
+    /// The translator cannot work without the task, all tasks are restarted when it ends or
+    /// stalls
+    RestartAll,
+}
+
+/// Liveness signal of a supervised task, beaten by the task on every iteration of its loop.
+#[derive(Debug, Clone)]
+pub struct Heartbeat(Arc<Mutex<Instant>>);
+
+impl Heartbeat {
+    pub fn new() -> Self {
+        Self(Arc::new(Mutex::new(Instant::now())))
+    }
+
+    pub fn beat(&self) {
+        let _ = self.0.safe_lock(|last| *last = Instant::now());
+    }
+
+    fn last(&self) -> Option<Instant> {
+        self.0.safe_lock(|last| *last).ok()
+    }
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug)]
+struct SupervisedTask {
+    name: String,
+    handle: AbortHandle,
+    started_at: Instant,
+    policy: RestartPolicy,
+    heartbeat: Option<(Heartbeat, Duration)>,
+}
+
+impl SupervisedTask {
+    fn state(&self, now: Instant) -> TaskState {
+        if self.handle.is_finished() {
+            return TaskState::Finished;
+        }
+        match &self.heartbeat {
+            Some((heartbeat, timeout)) => match heartbeat.last() {
+                Some(last) if now.saturating_duration_since(last) <= *timeout => TaskState::Running,
+                _ => TaskState::Stalled,
+            },
+            None => TaskState::Running,
+        }
+    }
+}
+
+/// Point in time view of a supervised task, used for logging.
+#[derive(Debug, Clone)]
+pub struct TaskInfo {
+    pub name: String,
+    pub state: TaskState,
+    pub policy: RestartPolicy,
+    pub age: Duration,
+    /// Time since the last heartbeat, for tasks that send one
+    pub since_heartbeat: Option<Duration>,
+    pub last_error: Option<String>,
+}
+
+/// Keeps track of the tasks spawned by the translator so they can be listed, checked for
+/// liveness and aborted on upstream reconnect. Finished tasks that may end are pruned whenever a
+/// new task is registered, so the list does not grow with every downstream that connects and
+/// disconnects.
+#[derive(Debug, Default)]
+pub struct TaskSupervisor {
+    tasks: Vec<SupervisedTask>,
+    /// Last error reported by each task or component
+    errors: HashMap<String, String>,
+    last_error: Option<(String, String)>,
+}
+
+impl TaskSupervisor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a spawned task under `name` that is allowed to end.
+    pub fn register(&mut self, name: &str, handle: AbortHandle) {
+        self.supervise(name, handle, RestartPolicy::Never, None);
+    }
+
+    /// Registers a spawned task under `name` with a restart policy and, for tasks that loop on a
+    /// timer, the heartbeat they beat and how old it can get before the task counts as stalled.
+    pub fn supervise(
+        &mut self,
+        name: &str,
+        handle: AbortHandle,
+        policy: RestartPolicy,
+        heartbeat: Option<(Heartbeat, Duration)>,
+    ) {
+        self.prune_finished();
+        self.tasks.push(SupervisedTask {
+            name: name.to_string(),
+            handle,
+            started_at: Instant::now(),
+            policy,
+            heartbeat,
+        });
+    }
+
+    /// Remembers the last error reported by a task or component, so it can be logged with the
+    /// task list.
+    pub fn record_error(&mut self, source: &str, error: String) {
+        self.errors.insert(source.to_string(), error.clone());
+        self.last_error = Some((source.to_string(), error));
+    }
+
+    pub fn last_error(&self) -> Option<&(String, String)> {
+        self.last_error.as_ref()
+    }
+
+    /// Last error reported by each task or component.
+    pub fn errors(&self) -> &HashMap<String, String> {
+        &self.errors
+    }
+
+    /// Returns the name of the first task that asks for a restart because it finished or
+    /// stalled, recording the reason as its last error.
+    pub fn needs_restart(&mut self, now: Instant) -> Option<String> {
+        let (name, state) = self
+            .tasks
+            .iter()
+            .filter(|t| t.policy == RestartPolicy::RestartAll)
+            .map(|t| (t.name.clone(), t.state(now)))
+            .find(|(_, state)| *state != TaskState::Running)?;
+        let reason = match state {
+            TaskState::Stalled => "task stalled",
+            _ => "task ended",
+        };
+        self.record_error(&name, reason.to_string());
+        Some(name)
+    }
+
+    /// Aborts every task that is still running and forgets about all of them.
+    pub fn abort_all(&mut self) {
+        let now = Instant::now();
+        while let Some(task) = self.tasks.pop() {
+            if task.state(now) != TaskState::Finished {
+                task.handle.abort();
+                warn!("Killed task: {:?}", task.name);
+            }
+        }
+    }
+
+    pub fn snapshot(&self) -> Vec<TaskInfo> {
+        let now = Instant::now();
+        self.tasks
+            .iter()
+            .map(|t| TaskInfo {
+                name: t.name.clone(),
+                state: t.state(now),
+                policy: t.policy,
+                age: now.saturating_duration_since(t.started_at),
+                since_heartbeat: t
+                    .heartbeat
+                    .as_ref()
+                    .and_then(|(heartbeat, _)| heartbeat.last())
+                    .map(|last| now.saturating_duration_since(last)),
+                last_error: self.errors.get(&t.name).cloned(),
+            })
+            .collect()
+    }
+
+    pub fn running(&self) -> usize {
+        let now = Instant::now();
+        self.tasks
+            .iter()
+            .filter(|t| t.state(now) != TaskState::Finished)
+            .count()
+    }
+
+    /// Forgets finished tasks, except the ones whose end has to trigger a restart.
+    fn prune_finished(&mut self) {
+        let now = Instant::now();
+        self.tasks
+            .retain(|t| t.policy == RestartPolicy::RestartAll || t.state(now) != TaskState::Finished);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn prunes_finished_and_aborts_running_tasks() {
+        let mut supervisor = TaskSupervisor::new();
+
+        let finished = tokio::task::spawn(async {});
+        supervisor.register("finished", finished.abort_handle());
+        finished.await.unwrap();
+
+        let running = tokio::task::spawn(std::future::pending::<()>());
+        supervisor.register("running", running.abort_handle());
+        assert_eq!(supervisor.snapshot().len(), 1);
+        assert_eq!(supervisor.running(), 1);
+
+        supervisor.record_error("upstream", "connection reset".to_string());
+        supervisor.abort_all();
+        assert!(supervisor.snapshot().is_empty());
+        assert!(running.await.unwrap_err().is_cancelled());
+        assert_eq!(
+            supervisor.last_error(),
+            Some(&("upstream".to_string(), "connection reset".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn restarts_on_critical_task_end_or_stall() {
+        let mut supervisor = TaskSupervisor::new();
+
+        let heartbeat = Heartbeat::new();
+        let beating = tokio::task::spawn(std::future::pending::<()>());
+        supervisor.supervise(
+            "beating",
+            beating.abort_handle(),
+            RestartPolicy::RestartAll,
+            Some((heartbeat.clone(), Duration::from_secs(60))),
+        );
+        let optional = tokio::task::spawn(async {});
+        supervisor.register("optional", optional.abort_handle());
+        optional.await.unwrap();
+        assert_eq!(supervisor.needs_restart(Instant::now()), None);
+
+        // no heartbeat for longer than the timeout
+        let later = Instant::now() + Duration::from_secs(61);
+        assert_eq!(supervisor.needs_restart(later), Some("beating".to_string()));
+        assert_eq!(supervisor.errors()["beating"], "task stalled");
+
+        let critical = tokio::task::spawn(async {});
+        supervisor.supervise("critical", critical.abort_handle(), RestartPolicy::RestartAll, None);
+        critical.await.unwrap();
+        heartbeat.beat();
+        assert_eq!(supervisor.needs_restart(Instant::now()), Some("critical".to_string()));
+
+        supervisor.abort_all();
+        assert!(beating.await.unwrap_err().is_cancelled());
+    }
+}
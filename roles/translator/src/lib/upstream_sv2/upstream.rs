@@ -4,18 +4,20 @@ use crate::{
         Error::{CodecNoise, InvalidExtranonce, PoisonLock, UpstreamIncoming},
         ProxyResult,
     },
+    proxy::WorkerLedger,
     proxy_config::UpstreamDifficultyConfig,
     status,
+    task_supervisor::{Heartbeat, RestartPolicy, TaskSupervisor},
     upstream_sv2::{EitherFrame, Message, StdFrame, UpstreamConnection},
 };
 use async_channel::{Receiver, Sender};
 use async_std::net::TcpStream;
 use binary_sv2::u256_from_int;
-use cdk::{nuts::KeySet, wallet::Wallet};
+use cdk::nuts::KeySet;
 use codec_sv2::{HandshakeRole, Initiator};
 use error_handling::handle_result;
 use key_utils::Secp256k1PublicKey;
-use mining_sv2::cashu::{BlindSignatureSet, Sv2KeySet};
+use mining_sv2::cashu::{BlindSignatureSet, BlindedMessageSet, Sv2KeySet};
 use network_helpers_sv2::Connection;
 use roles_logic_sv2::{
     common_messages_sv2::{Protocol, SetupConnection},
@@ -36,18 +38,19 @@ use roles_logic_sv2::{
     Error::NoUpstreamsConnected,
 };
 use std::{
-    net::SocketAddr, sync::{atomic::AtomicBool, Arc}
+    collections::VecDeque, net::SocketAddr, sync::{atomic::AtomicBool, Arc}
 };
-use tokio::{
-    task::AbortHandle,
-    time::{sleep, Duration},
-};
-use tracing::{error, info, warn};
+use tokio::time::{sleep, Duration};
+use tracing::{error, info, info_span, warn};
 
 use stratum_common::bitcoin::BlockHash;
 use stratum_common::bitcoin::hashes::hex::ToHex;
 
 pub static IS_NEW_JOB_HANDLED: AtomicBool = AtomicBool::new(true);
+/// Maximum number of submitted shares whose blinded messages are kept while waiting for the
+/// upstream `SubmitSharesSuccess`. Rejected shares never get a response, so the oldest entries
+/// are dropped first.
+const MAX_PENDING_BLINDED_MESSAGES: usize = 1024;
 /// Represents the currently active `prevhash` of the mining job being worked on OR being submitted
 /// from the Downstream role.
 #[derive(Debug, Clone)]
@@ -103,8 +106,15 @@ pub struct Upstream {
     // and the upstream just needs to occasionally check if it has changed more than
     // than the configured percentage
     pub(super) difficulty_config: Arc<Mutex<UpstreamDifficultyConfig>>,
-    task_collector: Arc<Mutex<Vec<(AbortHandle, String)>>>,
-    wallet: Arc<Wallet>,
+    task_collector: Arc<Mutex<TaskSupervisor>>,
+    /// Mint keyset received in the SV2 `OpenExtendedMiningChannelSuccess` message, used to verify
+    /// the DLEQ proofs of the blind signatures returned for each share.
+    keyset: Option<KeySet>,
+    /// Blinded messages of the shares sent upstream, keyed by share hash, needed to verify the
+    /// DLEQ proofs in the matching `SubmitSharesSuccess`.
+    pending_blinded_messages: VecDeque<([u8; 32], BlindedMessageSet)>,
+    /// Credits the ehash minted for each share to the SV1 worker that found it, in its wallet
+    worker_ledger: Arc<Mutex<WorkerLedger>>,
 }
 
 impl PartialEq for Upstream {
@@ -131,8 +141,8 @@ impl Upstream {
         tx_status: status::Sender,
         target: Arc<Mutex<Vec<u8>>>,
         difficulty_config: Arc<Mutex<UpstreamDifficultyConfig>>,
-        task_collector: Arc<Mutex<Vec<(AbortHandle, String)>>>,
-        wallet: Arc<Wallet>,
+        task_collector: Arc<Mutex<TaskSupervisor>>,
+        worker_ledger: Arc<Mutex<WorkerLedger>>,
     ) -> ProxyResult<'static, Arc<Mutex<Self>>> {
         // Connect to the SV2 Upstream role retry connection every 5 seconds.
         let socket = loop {
@@ -182,7 +192,9 @@ impl Upstream {
             target,
             difficulty_config,
             task_collector,
-            wallet,
+            keyset: None,
+            pending_blinded_messages: VecDeque::new(),
+            worker_ledger,
         })))
     }
 
@@ -297,18 +309,30 @@ impl Upstream {
         {
             let self_ = self_.clone();
             let tx_status = tx_status.clone();
+            let update_interval = self_
+                .safe_lock(|u| u.difficulty_config.clone())
+                .map_err(|_| PoisonLock)?
+                .safe_lock(|d| d.channel_diff_update_interval)
+                .map_err(|_| PoisonLock)?;
+            // the loop beats once per update interval, after a first wait of 10 seconds
+            let stall_timeout = Duration::from_secs(10 + 2 * update_interval as u64);
+            let heartbeat = Heartbeat::new();
+            let heartbeat_ = heartbeat.clone();
             let start_diff_management = tokio::task::spawn(async move {
                 // No need to start diff management immediatly
                 sleep(Duration::from_secs(10)).await;
                 loop {
+                    heartbeat_.beat();
                     handle_result!(tx_status, Self::try_update_hashrate(self_.clone()).await);
                 }
             });
             let _ = collector1.safe_lock(|a| {
-                a.push((
+                a.supervise(
+                    "start_diff_management",
                     start_diff_management.abort_handle(),
-                    "start_diff_management".to_string(),
-                ))
+                    RestartPolicy::RestartAll,
+                    Some((heartbeat, stall_timeout)),
+                )
             });
         }
 
@@ -456,7 +480,14 @@ impl Upstream {
             }
         });
         let _ = collector2
-            .safe_lock(|a| a.push((parse_incoming.abort_handle(), "parse_incoming".to_string())));
+            .safe_lock(|a| {
+                a.supervise(
+                    "parse_incoming",
+                    parse_incoming.abort_handle(),
+                    RestartPolicy::RestartAll,
+                    None,
+                )
+            });
 
         Ok(())
     }
@@ -513,6 +544,20 @@ impl Upstream {
                 let job_id = Self::get_job_id(&self_);
                 sv2_submit.job_id = handle_result!(tx_status, handle_result!(tx_status, job_id));
 
+                match BlindedMessageSet::try_from(sv2_submit.blinded_messages.clone()) {
+                    Ok(blinded_messages) => {
+                        let mut hash = [0u8; 32];
+                        hash.copy_from_slice(sv2_submit.hash.inner_as_ref());
+                        handle_result!(
+                            tx_status,
+                            self_
+                                .safe_lock(|s| s.remember_blinded_messages(hash, blinded_messages))
+                                .map_err(|_e| PoisonLock)
+                        );
+                    }
+                    Err(e) => warn!("Submitting share with undecodable blinded messages: {:?}", e),
+                }
+
                 let message = Message::Mining(
                     roles_logic_sv2::parsers::Mining::SubmitSharesExtended(sv2_submit),
                 );
@@ -532,8 +577,68 @@ impl Upstream {
             }
         });
         let _ = task_collector
-            .safe_lock(|a| a.push((handle_submit.abort_handle(), "handle_submit".to_string())));
+            .safe_lock(|a| {
+                a.supervise(
+                    "handle_submit",
+                    handle_submit.abort_handle(),
+                    RestartPolicy::RestartAll,
+                    None,
+                )
+            });
+
+        Ok(())
+    }
 
+    fn remember_blinded_messages(&mut self, hash: [u8; 32], blinded_messages: BlindedMessageSet) {
+        if self.pending_blinded_messages.len() >= MAX_PENDING_BLINDED_MESSAGES {
+            self.pending_blinded_messages.pop_front();
+        }
+        self.pending_blinded_messages.push_back((hash, blinded_messages));
+    }
+
+    /// Checks the DLEQ proof of every blind signature against the mint keyset and the blinded
+    /// message submitted with the share, so that signatures forged or altered on the way from
+    /// the mint are never turned into ehash proofs. Signatures without a proof, or for a share
+    /// whose blinded messages were already evicted, are refused as well.
+    fn verify_blind_signatures(
+        &mut self,
+        hash: [u8; 32],
+        blind_signatures: &BlindSignatureSet,
+    ) -> Result<(), RolesLogicError> {
+        let keyset = self
+            .keyset
+            .as_ref()
+            .ok_or(RolesLogicError::KeysetError("No mint keyset received".to_string()))?;
+        let position = self
+            .pending_blinded_messages
+            .iter()
+            .position(|(pending_hash, _)| *pending_hash == hash)
+            .ok_or(RolesLogicError::KeysetError(format!(
+                "No blinded messages for share {}",
+                hash.to_hex()
+            )))?;
+        let (_, blinded_messages) = self
+            .pending_blinded_messages
+            .remove(position)
+            .expect("position is in bounds");
+
+        for signature in blind_signatures.items.iter().flatten() {
+            let amount = u64::from(signature.amount);
+            let mint_pubkey = keyset.keys.amount_key(signature.amount).ok_or(
+                RolesLogicError::KeysetError(format!("No mint key for amount {}", amount)),
+            )?;
+            let blinded_message = blinded_messages.get(amount).ok_or(
+                RolesLogicError::KeysetError(format!("No blinded message for amount {}", amount)),
+            )?;
+            signature
+                .verify_dleq(mint_pubkey, blinded_message.blinded_secret)
+                .map_err(|e| {
+                    RolesLogicError::KeysetError(format!(
+                        "Invalid DLEQ proof for amount {}: {}",
+                        amount, e
+                    ))
+                })?;
+        }
         Ok(())
     }
 
@@ -697,16 +802,23 @@ impl ParseUpstreamMiningMessages<Downstream, NullDownstreamMiningSelector, NoRou
         self.extranonce_prefix = Some(m.extranonce_prefix.to_vec());
 
         let m_static = m.into_static();
-        let wallet_clone = self.wallet.clone();
         let sv2_keyset = Sv2KeySet::try_from(m_static.keyset.clone())
             .map_err(|e| RolesLogicError::KeysetError(format!("{:?}", e)))?;
         let keyset = KeySet::try_from(sv2_keyset)
             .map_err(|e| RolesLogicError::KeysetError(e.to_string()))?;
 
+        self.keyset = Some(keyset.clone());
+        // workers that connect later get the keyset when their wallet is created
+        let wallets = self
+            .worker_ledger
+            .safe_lock(|l| l.set_keys(keyset.keys.clone()))
+            .map_err(|e| RolesLogicError::PoisonLock(e.to_string()))?;
         tokio::spawn(async move {
-            if let Err(e) = wallet_clone.add_keyset(keyset.keys, true, 0).await {
-                warn!("Failed to add keyset to wallet: {:?}", e);
-            };
+            for wallet in wallets {
+                if let Err(e) = wallet.add_keyset(keyset.keys.clone(), true, 0).await {
+                    warn!("Failed to add keyset to wallet: {:?}", e);
+                };
+            }
         });
 
         let m = Mining::OpenExtendedMiningChannelSuccess(m_static);
@@ -754,19 +866,43 @@ impl ParseUpstreamMiningMessages<Downstream, NullDownstreamMiningSelector, NoRou
         &mut self,
         m: roles_logic_sv2::mining_sv2::SubmitSharesSuccess,
     ) -> Result<roles_logic_sv2::handlers::mining::SendTo<Downstream>, RolesLogicError> {
-        let wallet = self.wallet.clone();
+        // TODO is it better to recalculate this value from the share or to pass it over the wire?
+        let share_hash = m.hash.to_vec().to_hex();
+        // same hash the pool logs the share under
+        let span = info_span!(
+            "share",
+            channel = m.channel_id,
+            sequence = m.last_sequence_number,
+            hash = %share_hash,
+        );
+        let _enter = span.enter();
 
+        // a bad signature set only costs the ehash of this share, the connection stays up
         let blind_signature_set: BlindSignatureSet = match m.blind_signatures.try_into() {
             Ok(signatures) => signatures,
             Err(e) => {
-                // TODO use a better error
-                return Err(RolesLogicError::KeysetError(format!("Error minting ehash {:?}", e)));
+                error!("Failed to decode blind signatures, no ehash minted: {:?}", e);
+                return Ok(SendTo::None(None));
             }
         };
 
-        // TODO is it better to recalculate this value from the share or to pass it over the wire?
-        let share_hash = m.hash.to_vec().to_hex();
-        
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(m.hash.inner_as_ref());
+        if let Err(e) = self.verify_blind_signatures(hash, &blind_signature_set) {
+            error!("Rejected blind signatures, no ehash minted: {:?}", e);
+            return Ok(SendTo::None(None));
+        }
+
+        // the premint secrets of the share are in the wallet of the worker that found it
+        let wallet = match self.worker_ledger.safe_lock(|l| l.share_wallet(hash)) {
+            Ok(Some(wallet)) => wallet,
+            Ok(None) => {
+                error!("No worker wallet for share {}, no ehash minted", share_hash);
+                return Ok(SendTo::None(None));
+            }
+            Err(e) => return Err(RolesLogicError::PoisonLock(e.to_string())),
+        };
+
         let result = tokio::task::block_in_place(|| {
             tokio::runtime::Handle::current().block_on(
                 wallet.gen_ehash_proofs(
@@ -777,21 +913,41 @@ impl ParseUpstreamMiningMessages<Downstream, NullDownstreamMiningSelector, NoRou
         });
         
         match result {
-            Ok(amount) => info!("Hashpool minted ehash tokens for share {} with value {}", share_hash, u64::from(amount)),
-            Err(e) => {
-                // TODO use a better error
-                return Err(RolesLogicError::KeysetError(format!("Error minting ehash {:?}", e)));
+            Ok(amount) => {
+                let amount = u64::from(amount);
+                info!("Hashpool minted ehash tokens for share {} with value {}", share_hash, amount);
+                match self.worker_ledger.safe_lock(|l| l.on_ehash_minted(hash, amount)) {
+                    Ok(Ok(Some((worker, balance)))) => info!(
+                        "Credited {} ehash to worker {}, {} ehash over {} shares",
+                        amount, worker, balance.ehash, balance.shares
+                    ),
+                    Ok(Ok(None)) => {
+                        warn!("Minted ehash for share {} of an unknown worker", share_hash)
+                    }
+                    Ok(Err(e)) => error!("Failed to append to worker ledger: {}", e),
+                    Err(e) => error!("Failed to lock worker ledger: {}", e),
+                }
             }
+            Err(e) => error!("Error minting ehash: {:?}", e),
         }
 
         Ok(SendTo::None(None))
     }
 
     /// Handles the SV2 `SubmitSharesError` message.
+    /// Logs a share refused by the pool, shares accepted without ehash such as the ones diverted
+    /// by the pool circuit breaker also end up here. The share stays pending, so the signatures
+    /// the pool sends later when its operator approves a diverted issuance are still minted.
     fn handle_submit_shares_error(
         &mut self,
-        _m: roles_logic_sv2::mining_sv2::SubmitSharesError,
+        m: roles_logic_sv2::mining_sv2::SubmitSharesError,
     ) -> Result<roles_logic_sv2::handlers::mining::SendTo<Downstream>, RolesLogicError> {
+        warn!(
+            "Pool rejected share {} on channel {}: {}",
+            m.sequence_number,
+            m.channel_id,
+            String::from_utf8_lossy(&m.error_code.to_vec())
+        );
         Ok(SendTo::None(None))
     }
 
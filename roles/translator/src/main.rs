@@ -4,7 +4,7 @@ mod lib;
 
 use args::Args;
 use error::{Error, ProxyResult};
-pub use lib::{downstream_sv1, error, proxy, proxy_config, status, upstream_sv2};
+pub use lib::{downstream_sv1, error, proxy, proxy_config, status, task_supervisor, upstream_sv2};
 use proxy_config::ProxyConfig;
 
 use ext_config::{Config, File, FileFormat};